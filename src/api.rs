@@ -0,0 +1,183 @@
+//! JSON read API
+//!
+//! Every page and index url returns JSON instead of html when
+//! requested with `Accept: application/json` or `?format=json`.
+//! Tag urls are not redirected to their precise form, the response
+//! tells what matched instead:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "matching_tags": ["idea"],
+//!   "unmatched_tags": ["nonexistent"],
+//!   "narrowing_tags": {"brainwiki": 2, "rust": 1},
+//!   "pages": [
+//!     {
+//!       "title": "My idea about brainwiki",
+//!       "url": "/idea/brainwiki",
+//!       "tags": ["idea", "brainwiki"],
+//!       "created": "2018-07-10T14:30:00+02:00",
+//!       "modified": "2018-07-11T09:12:44.123+02:00"
+//!     }
+//!   ],
+//!   "page": null
+//! }
+//! ```
+//!
+//! `pages` lists all matching pages, sorted by title. When exactly
+//! one page matched, `page` holds it in full: the fields of
+//! `pages` entries, plus `md` and rendered `html`. Timestamps are
+//! RFC 3339, or `null` when unknown.
+//!
+//! `version` is bumped on any incompatible change of this schema;
+//! adding fields is not considered one.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Local};
+
+use crate::{
+    data::{self, MatchType},
+    page::Page,
+};
+
+pub const VERSION: u32 = 1;
+
+#[derive(Debug, Serialize)]
+pub struct PageSummary {
+    pub title: String,
+    pub url: String,
+    pub tags: Vec<String>,
+    pub created: Option<DateTime<Local>>,
+    pub modified: Option<DateTime<Local>>,
+}
+
+impl<'a> From<&'a Page> for PageSummary {
+    fn from(page: &Page) -> Self {
+        PageSummary {
+            title: page.title.clone(),
+            url: page.url(),
+            tags: page.tags.clone(),
+            created: page.created,
+            modified: page.modified,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct FullPage {
+    pub title: String,
+    pub url: String,
+    pub tags: Vec<String>,
+    pub created: Option<DateTime<Local>>,
+    pub modified: Option<DateTime<Local>>,
+    pub md: String,
+    pub html: String,
+}
+
+impl<'a> From<&'a Page> for FullPage {
+    fn from(page: &Page) -> Self {
+        FullPage {
+            title: page.title.clone(),
+            url: page.url(),
+            tags: page.tags.clone(),
+            created: page.created,
+            modified: page.modified,
+            md: page.md.clone(),
+            html: page.html.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct MatchResponse {
+    pub version: u32,
+    pub matching_tags: Vec<String>,
+    pub unmatched_tags: Vec<String>,
+    /// Sorted, for stable output
+    pub narrowing_tags: BTreeMap<String, usize>,
+    pub pages: Vec<PageSummary>,
+    pub page: Option<FullPage>,
+}
+
+impl MatchResponse {
+    pub fn new(match_: &data::Match, state: &data::State) -> Self {
+        let page = |page_id| state.pages_by_id.get(page_id).unwrap();
+
+        let mut pages: Vec<&Page> = match match_.type_ {
+            MatchType::None => vec![],
+            MatchType::One(ref page_id) => vec![page(page_id)],
+            MatchType::Many(ref page_ids) => {
+                page_ids.iter().map(page).collect()
+            }
+        };
+        pages.sort_by(|n, m| n.title.cmp(&m.title));
+
+        MatchResponse {
+            version: VERSION,
+            matching_tags: match_.matching_tags.clone(),
+            unmatched_tags: match_.unmatched_tags.clone(),
+            narrowing_tags: match_
+                .narrowing_tags
+                .iter()
+                .map(|(tag, count)| (tag.clone(), *count))
+                .collect(),
+            pages: pages
+                .iter()
+                .map(|page| PageSummary::from(*page))
+                .collect(),
+            page: match match_.type_ {
+                MatchType::One(ref page_id) => {
+                    Some(FullPage::from(page(page_id)))
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+#[test]
+fn match_response() {
+    use crate::page_filter::PageFilter;
+    use serde_json::json;
+    use std::fs;
+
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    fs::write(dir.path().join("a.md"), "# Idea\n#idea #brainwiki")
+        .unwrap();
+    fs::write(dir.path().join("b.md"), "# Other\n#idea #rust").unwrap();
+
+    let mut state = data::State::new();
+    state
+        .insert_from_dir(
+            dir.path(),
+            &PageFilter::load(dir.path()).unwrap(),
+        )
+        .unwrap();
+
+    let match_ = state.find_best_match(vec!["idea".into()], false);
+    let json =
+        serde_json::to_value(&MatchResponse::new(&match_, &state))
+            .unwrap();
+    assert_eq!(json["version"], 1);
+    assert_eq!(json["matching_tags"], json!(["idea"]));
+    assert_eq!(
+        json["narrowing_tags"],
+        json!({"brainwiki": 1, "rust": 1})
+    );
+    assert_eq!(json["pages"][0]["title"], "Idea");
+    assert_eq!(json["pages"][1]["url"], "/idea/rust");
+    assert!(json["page"].is_null());
+    assert!(json["pages"][0]["modified"].is_string());
+
+    let match_ = state.find_best_match(
+        vec!["idea".into(), "rust".into(), "nonexistent".into()],
+        true,
+    );
+    let json =
+        serde_json::to_value(&MatchResponse::new(&match_, &state))
+            .unwrap();
+    assert_eq!(json["unmatched_tags"], json!(["nonexistent"]));
+    assert_eq!(json["page"]["md"], "# Other\n#idea #rust");
+    assert!(json["page"]["html"].as_str().unwrap().contains("Other"));
+}
@@ -0,0 +1,113 @@
+//! Append-only log of logins and changes, kept in the data dir
+
+use chrono::Local;
+use std::{
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use crate::Result;
+
+pub const AUDIT_LOG_FILE_NAME: &str = "audit.log";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// RFC 3339
+    pub time: String,
+    /// Client address
+    pub addr: String,
+    /// How the client was authorized, like `token capture`
+    pub who: String,
+    pub action: String,
+}
+
+/// Keep each entry on one line and its fields apart
+fn sanitize(field: &str) -> String {
+    field
+        .chars()
+        .map(|ch| match ch {
+            '\t' | '\n' | '\r' => ' ',
+            ch => ch,
+        })
+        .collect()
+}
+
+pub struct AuditLog {
+    path: PathBuf,
+    /// Keeps entries from interleaving
+    lock: Mutex<()>,
+}
+
+impl AuditLog {
+    pub fn new(data_dir: &Path) -> Self {
+        AuditLog {
+            path: data_dir.join(AUDIT_LOG_FILE_NAME),
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn record(
+        &self,
+        addr: &str,
+        who: &str,
+        action: &str,
+    ) -> Result<()> {
+        let line = format!(
+            "{}\t{}\t{}\t{}\n",
+            Local::now().to_rfc3339(),
+            sanitize(addr),
+            sanitize(who),
+            sanitize(action)
+        );
+
+        let _lock = self.lock.lock().unwrap();
+        let mut file = OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(&self.path)?;
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+
+    /// Up to `limit` entries, newest first
+    pub fn recent(&self, limit: usize) -> Result<Vec<Entry>> {
+        if !self.path.exists() {
+            return Ok(vec![]);
+        }
+        let content = fs::read_to_string(&self.path)?;
+        Ok(content
+            .lines()
+            .rev()
+            .filter_map(|line| {
+                let mut fields = line.splitn(4, '\t');
+                Some(Entry {
+                    time: fields.next()?.to_owned(),
+                    addr: fields.next()?.to_owned(),
+                    who: fields.next()?.to_owned(),
+                    action: fields.next()?.to_owned(),
+                })
+            })
+            .take(limit)
+            .collect())
+    }
+}
+
+#[test]
+fn record_and_read() {
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let log = AuditLog::new(dir.path());
+    assert_eq!(log.recent(10).unwrap(), vec![]);
+
+    log.record("127.0.0.1", "session", "Login").unwrap();
+    log.record("127.0.0.1", "session", "Update\tidea/\nrust")
+        .unwrap();
+
+    let entries = log.recent(10).unwrap();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].action, "Update idea/ rust");
+    assert_eq!(entries[1].action, "Login");
+    assert_eq!(entries[1].who, "session");
+    assert_eq!(log.recent(1).unwrap().len(), 1);
+}
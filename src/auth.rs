@@ -0,0 +1,78 @@
+//! Who may do what
+//!
+//! Every permission decision goes through `access`: web handlers
+//! extract an `Access` from the request and compare it with what
+//! they need, templates show edit controls based on it.
+
+use crate::settings::TokenScope;
+
+/// What a request is allowed to do, from least to most
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Access {
+    /// Only pages
+    Anonymous,
+    /// Also pages for the wiki owner, like trash and status
+    Read,
+    /// Also any changes
+    Write,
+}
+
+impl Access {
+    pub fn can_write(self) -> bool {
+        self >= Access::Write
+    }
+}
+
+/// Everything a request can prove about who sent it
+#[derive(Debug, Clone, Default)]
+pub struct Credentials {
+    /// Running with `--local`, everyone is the owner
+    pub local: bool,
+    /// Session logged in with the password
+    pub logged_in: bool,
+    /// Valid API token
+    pub token: Option<TokenScope>,
+}
+
+pub fn access(credentials: &Credentials) -> Access {
+    if credentials.local || credentials.logged_in {
+        return Access::Write;
+    }
+
+    match credentials.token {
+        Some(TokenScope::ReadWrite) => Access::Write,
+        Some(TokenScope::Read) => Access::Read,
+        None => Access::Anonymous,
+    }
+}
+
+#[test]
+fn access_by_credentials() {
+    let anonymous = Credentials::default();
+    assert_eq!(access(&anonymous), Access::Anonymous);
+
+    let local = Credentials {
+        local: true,
+        ..Credentials::default()
+    };
+    assert_eq!(access(&local), Access::Write);
+
+    let logged_in = Credentials {
+        logged_in: true,
+        ..Credentials::default()
+    };
+    assert_eq!(access(&logged_in), Access::Write);
+
+    let read_token = Credentials {
+        token: Some(TokenScope::Read),
+        ..Credentials::default()
+    };
+    assert_eq!(access(&read_token), Access::Read);
+    assert!(!access(&read_token).can_write());
+
+    let write_token = Credentials {
+        token: Some(TokenScope::ReadWrite),
+        ..Credentials::default()
+    };
+    assert_eq!(access(&write_token), Access::Write);
+}
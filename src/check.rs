@@ -0,0 +1,188 @@
+//! Finding links that don't resolve to what they used to
+use std::{fmt, path::PathBuf};
+
+use crate::{
+    data::{LookupOutcome, MatchType, State},
+    markdown::Link,
+    page::Page,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LinkStatus {
+    Ok,
+    /// No page has any of the tags
+    Missing,
+    /// Exact link matching more than one page
+    Ambiguous(usize),
+    /// Resolved only after dropping some tags
+    Broadened(Vec<String>),
+}
+
+impl LinkStatus {
+    pub fn is_ok(&self) -> bool {
+        *self == LinkStatus::Ok
+    }
+}
+
+impl fmt::Display for LinkStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LinkStatus::Ok => write!(f, "ok"),
+            LinkStatus::Missing => write!(f, "missing"),
+            LinkStatus::Ambiguous(n) => {
+                write!(f, "ambiguous ({} pages)", n)
+            }
+            LinkStatus::Broadened(tags) => {
+                write!(f, "broadened (dropped: {})", tags.join(", "))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LinkReport {
+    pub source: Page,
+    pub source_path: PathBuf,
+    pub link: Link,
+    pub status: LinkStatus,
+}
+
+pub fn check_link(state: &State, link: &Link) -> LinkStatus {
+    let (tags, prefer_exact) = state.url_tags(link.path());
+    if prefer_exact {
+        if let LookupOutcome::One(_) = state.lookup_exact(tags.clone())
+        {
+            return LinkStatus::Ok;
+        }
+    }
+
+    let match_ = state.find_best_match(tags, prefer_exact);
+    if match_.matching_tags.is_empty() {
+        return LinkStatus::Missing;
+    }
+
+    match match_.type_ {
+        MatchType::None => LinkStatus::Missing,
+        MatchType::Many(ref ids) if prefer_exact => {
+            LinkStatus::Ambiguous(ids.len())
+        }
+        _ => if match_.has_unmatched_tags() {
+            LinkStatus::Broadened(match_.unmatched_tags)
+        } else {
+            LinkStatus::Ok
+        },
+    }
+}
+
+/// All problematic links, sorted by source file
+pub fn check_links(state: &State) -> Vec<LinkReport> {
+    let mut reports = vec![];
+    for (page_id, page) in &state.pages_by_id {
+        for link in &page.links {
+            let status = check_link(state, link);
+            if status.is_ok() {
+                continue;
+            }
+            reports.push(LinkReport {
+                source: page.clone(),
+                source_path: state
+                    .path_by_id
+                    .get(page_id)
+                    .unwrap()
+                    .clone(),
+                link: link.clone(),
+                status: status,
+            });
+        }
+    }
+    reports.sort_by(|n, m| n.source_path.cmp(&m.source_path));
+    reports
+}
+
+#[test]
+fn link_statuses() {
+    use crate::page_filter::PageFilter;
+    use std::fs;
+
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    fs::write(dir.path().join("a.md"), "#idea #brainwiki").unwrap();
+    fs::write(dir.path().join("b.md"), "#idea #rust").unwrap();
+    fs::write(dir.path().join("c.md"), "#idea #rust #fast").unwrap();
+
+    let mut state = State::new();
+    state
+        .insert_from_dir(
+            dir.path(),
+            &PageFilter::load(dir.path()).unwrap(),
+        )
+        .unwrap();
+
+    let status = |state: &State, url: &str| {
+        let md = format!("[x]({})", url);
+        let link = Page::from_markdown(md, &Default::default()).links.pop().unwrap();
+        check_link(state, &link)
+    };
+
+    assert_eq!(status(&state, "/idea/brainwiki"), LinkStatus::Ok);
+    assert_eq!(status(&state, "/rust/idea"), LinkStatus::Ok);
+    assert_eq!(status(&state, "/idea/"), LinkStatus::Ok);
+    assert_eq!(status(&state, "/nothing"), LinkStatus::Missing);
+    assert_eq!(status(&state, "/idea"), LinkStatus::Ambiguous(3));
+    assert_eq!(
+        status(&state, "/brainwiki/gone"),
+        LinkStatus::Broadened(vec!["gone".into()])
+    );
+}
+
+#[test]
+fn hierarchical_links() {
+    use crate::{
+        page_filter::PageFilter, settings::TagSettings, tag::Grammar,
+    };
+    use std::fs;
+
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    fs::write(dir.path().join("a.md"), "# A\n#lang/rust").unwrap();
+    fs::write(dir.path().join("b.md"), "# B\n#lang/go").unwrap();
+    fs::write(dir.path().join("s.md"), "# S\n#notes [x](/lang/rust)")
+        .unwrap();
+
+    let mut state = State::new();
+    state.set_tag_grammar(
+        Grammar::new(&TagSettings {
+            hierarchy_separator: Some('/'),
+            ..TagSettings::default()
+        })
+        .unwrap(),
+    );
+    state
+        .insert_from_dir(
+            dir.path(),
+            &PageFilter::load(dir.path()).unwrap(),
+        )
+        .unwrap();
+    let id_of = |title: &str| {
+        *state
+            .pages_by_id
+            .iter()
+            .find(|(_, page)| page.title == title)
+            .unwrap()
+            .0
+    };
+    let (rust, source) = (id_of("A"), id_of("S"));
+
+    // the link leads where opening its url does
+    let link = state.pages_by_id[&source].links[0].clone();
+    let (tags, prefer_exact) = state.url_tags(link.path());
+    assert_eq!(
+        state.find_best_match(tags, prefer_exact).type_,
+        MatchType::One(rust)
+    );
+    assert_eq!(check_link(&state, &link), LinkStatus::Ok);
+    let backlinks: Vec<_> = state
+        .backlinks(rust)
+        .iter()
+        .map(|page| page.title.clone())
+        .collect();
+    assert_eq!(backlinks, vec!["S".to_string()]);
+}
@@ -1,5 +1,5 @@
 use std::{
-    collections::{HashMap, HashSet},
+    collections::{HashMap, HashSet, VecDeque},
     fs,
     path::{Path, PathBuf},
     sync, thread,
@@ -8,9 +8,20 @@ use std::{
 use notify::{
     DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher,
 };
+use log::{debug, info, warn};
 use std::{fs::File, io::Write, time::Duration};
 
-use crate::{page::Page, Result};
+use crate::{
+    git::GitRepo,
+    history::History,
+    markdown::Link,
+    page::Page,
+    page_filter::{normalize_path, PageFilter},
+    query::Query,
+    search,
+    tag::{self, Grammar},
+    Result,
+};
 
 #[derive(
     From,
@@ -27,6 +38,95 @@ use crate::{page::Page, Result};
 pub struct PageId(u32);
 pub type NarrowingTagsSet = HashMap<String, usize>;
 
+/// Name of the directory (inside data dir) deleted pages are moved to
+pub const TRASH_DIR_NAME: &str = ".trash";
+
+pub fn trash_dir(data_dir: &Path) -> PathBuf {
+    data_dir.join(TRASH_DIR_NAME)
+}
+
+/// Find a `<path_text>[_...].md` path in `dir` that is not taken yet
+fn free_path_in(dir: &Path, path_text: &str) -> PathBuf {
+    let mut path_text = path_text.to_owned();
+    loop {
+        let dst_path = dir.join(format!("{}.md", path_text));
+        if !dst_path.exists() {
+            break dst_path;
+        }
+        path_text += "_";
+    }
+}
+
+fn file_stem_str(path: &Path) -> Result<&str> {
+    path.file_stem().and_then(|s| s.to_str()).ok_or_else(|| {
+        format_err!("Invalid file name: {}", path.display())
+    })
+}
+
+#[derive(Debug, Clone)]
+pub struct TrashedPage {
+    pub file_name: String,
+    pub page: Page,
+}
+
+pub fn list_trash(
+    data_dir: &Path,
+    grammar: &Grammar,
+) -> Result<Vec<TrashedPage>> {
+    let dir = trash_dir(data_dir);
+    let mut pages = vec![];
+    if !dir.exists() {
+        return Ok(pages);
+    }
+
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if !path.is_file()
+            || path.extension().and_then(|e| e.to_str())
+                != Some("md")
+        {
+            continue;
+        }
+        let file_name =
+            match path.file_name().and_then(|n| n.to_str()) {
+                Some(file_name) => file_name.to_owned(),
+                None => continue,
+            };
+        match Page::read_from_file(&path, grammar) {
+            Ok(page) => pages.push(TrashedPage {
+                file_name: file_name,
+                page: page,
+            }),
+            Err(e) => {
+                warn!("Skipping trashed {}: {}", path.display(), e)
+            }
+        }
+    }
+    pages.sort_by(|n, m| n.page.title.cmp(&m.page.title));
+    Ok(pages)
+}
+
+/// Number of recent load errors kept for `/~status`
+const MAX_RECENT_ERRORS: usize = 20;
+
+/// How long to wait before re-creating a failed fs watcher
+const WATCHER_RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone)]
+pub struct LoadError {
+    pub time: chrono::DateTime<chrono::Local>,
+    pub path: Option<PathBuf>,
+    pub msg: String,
+}
+
+/// Problems encountered while loading and watching the data dir
+#[derive(Default, Debug, Clone)]
+pub struct LoadStatus {
+    pub recent_errors: VecDeque<LoadError>,
+    /// Files that could not be read, with the last error
+    pub failed_files: HashMap<PathBuf, String>,
+}
+
 #[derive(Default)]
 pub struct State {
     pub pages_by_id: HashMap<PageId, Page>,
@@ -35,6 +135,15 @@ pub struct State {
     tag_sets: HashMap<String, HashSet<PageId>>,
     next_page_id: PageId,
     all_pages: HashSet<PageId>,
+    text_index: search::TextIndex,
+    /// Tag -> pages having links using it
+    links_by_tag: HashMap<String, HashSet<PageId>>,
+    /// Complete (sorted) indexed tag set -> pages having exactly
+    /// these tags
+    pages_by_tag_set: HashMap<Vec<String>, HashSet<PageId>>,
+    pub load_status: LoadStatus,
+    tag_grammar: Grammar,
+    tag_rules: tag::Rules,
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -73,8 +182,13 @@ impl Match {
     }
 
     pub fn to_precise_url(&self, prefer_exact: bool) -> String {
-        let mut location = String::from("/")
-            + self.matching_tags.join("/").as_str();
+        let segments: Vec<String> = self
+            .matching_tags
+            .iter()
+            .map(|t| tag::to_url_segment(t))
+            .collect();
+        let mut location =
+            String::from("/") + segments.join("/").as_str();
         if !prefer_exact {
             location += "/";
         }
@@ -102,44 +216,130 @@ impl State {
         Default::default()
     }
 
+    pub fn tag_grammar(&self) -> &Grammar {
+        &self.tag_grammar
+    }
+
+    /// Grammar for pages loaded from now on
+    pub fn set_tag_grammar(&mut self, grammar: Grammar) {
+        self.tag_grammar = grammar;
+    }
+
+    /// Aliases and implications for pages inserted from now on
+    pub fn set_tag_rules(&mut self, rules: tag::Rules) {
+        self.tag_rules = rules;
+    }
+
+    /// `tags` with aliases replaced by their canonical tags
+    pub fn canonical_tags(&self, tags: &[String]) -> Vec<String> {
+        tags.iter()
+            .map(|tag| self.tag_rules.canonical(tag).to_owned())
+            .collect()
+    }
+
+    /// Canonicalize tags of `page` and its links, add implied tags
+    fn apply_tag_rules(&self, page: &mut Page) {
+        page.tags = self.canonical_tags(&page.tags);
+        page.tags.sort();
+        page.tags.dedup();
+        for link in &mut page.links {
+            link.tags = self.canonical_tags(&link.tags);
+        }
+        page.derived_tags = self.tag_rules.implied(&page.tags);
+    }
+
+    /// Load all pages from `dir_path` and its subdirectories
     pub fn insert_from_dir(
         &mut self,
         dir_path: &Path,
+        filter: &PageFilter,
+    ) -> Result<()> {
+        self.load_dir(dir_path, filter)
+    }
+
+    /// Load all pages under `dir_path`
+    ///
+    /// Only failing to read `dir_path` itself is an error, unreadable
+    /// subdirectories and files are recorded in `load_status`.
+    fn load_dir(
+        &mut self,
+        dir_path: &Path,
+        filter: &PageFilter,
     ) -> Result<()> {
         for entry in fs::read_dir(dir_path)? {
-            let entry = entry?;
-            let path = entry.path();
-            if path.is_file()
-                && path.extension().and_then(|e| e.to_str())
-                    == Some("md")
-            {
-                self.insert_from_file(&path)?;
+            let path = match entry {
+                Ok(entry) => entry.path(),
+                Err(e) => {
+                    self.record_error(Some(dir_path), &e.into());
+                    continue;
+                }
+            };
+            if path.is_dir() {
+                if filter.is_ignored(&path, true) {
+                    continue;
+                }
+                if let Err(e) = self.load_dir(&path, filter) {
+                    self.record_error(Some(&path), &e);
+                }
+            } else if path.is_file() && filter.is_page(&path) {
+                if let Err(e) = self.insert_from_file(&path) {
+                    self.record_error(Some(&path), &e);
+                }
             }
         }
         Ok(())
     }
 
+    pub fn record_error(
+        &mut self,
+        path: Option<&Path>,
+        e: &failure::Error,
+    ) {
+        let msg = e.to_string();
+        let path = path.map(normalize_path);
+        match path {
+            Some(ref path) => {
+                warn!("Failed to load {}: {}", path.display(), msg);
+                self.load_status
+                    .failed_files
+                    .insert(path.clone(), msg.clone());
+            }
+            None => warn!("Fs watcher error: {}", msg),
+        }
+
+        let recent_errors = &mut self.load_status.recent_errors;
+        recent_errors.push_back(LoadError {
+            time: chrono::Local::now(),
+            path: path,
+            msg: msg,
+        });
+        while recent_errors.len() > MAX_RECENT_ERRORS {
+            recent_errors.pop_front();
+        }
+    }
+
+    pub fn clear_error(&mut self, path: &Path) {
+        self.load_status.failed_files.remove(&normalize_path(path));
+    }
+
     pub fn insert_from_file(
         &mut self,
         md_path: &Path,
     ) -> Result<()> {
-        let page = Page::read_from_file(md_path)?;
+        let page =
+            Page::read_from_file(md_path, &self.tag_grammar)?;
 
         self.insert(page, &md_path.canonicalize()?);
         Ok(())
     }
 
-    fn insert(&mut self, page: Page, path: &Path) -> PageId {
+    fn insert(&mut self, mut page: Page, path: &Path) -> PageId {
+        self.apply_tag_rules(&mut page);
         let page_id = self.next_page_id;
         self.next_page_id += 1.into();
         self.all_pages.insert(page_id);
 
-        for tag in page.tags.iter() {
-            self.tag_sets
-                .entry(tag.clone())
-                .or_insert(Default::default())
-                .insert(page_id);
-        }
+        self.index_page(page_id, &page);
         debug_assert!(
             path.extension().and_then(|e| e.to_str())
                 == Some("md")
@@ -150,19 +350,214 @@ impl State {
         page_id
     }
 
+    /// Replace the content of an existing page, keeping its `PageId`
+    ///
+    /// Returns the previous version.
+    fn update(&mut self, page_id: PageId, mut page: Page) -> Page {
+        self.apply_tag_rules(&mut page);
+        let old_page = self.pages_by_id.remove(&page_id).unwrap();
+        self.unindex_page(page_id, &old_page);
+        self.index_page(page_id, &page);
+        self.pages_by_id.insert(page_id, page);
+        old_page
+    }
+
     fn remove(&mut self, page_id: PageId) {
         let page = self.pages_by_id.remove(&page_id).unwrap();
-        for tag in page.tags.iter() {
-            self.tag_sets
-                .get_mut(&tag.clone())
-                .unwrap()
-                .remove(&page_id);
-        }
+        self.unindex_page(page_id, &page);
         self.all_pages.remove(&page_id);
         let path = self.path_by_id.remove(&page_id).unwrap();
         self.pages_by_path.remove(&path).unwrap();
     }
 
+    /// Written and derived tags of `page` along with all their
+    /// ancestors
+    fn indexed_tags<'a>(&self, page: &'a Page) -> HashSet<&'a str> {
+        let separator = self.tag_grammar.separator();
+        page.tags
+            .iter()
+            .chain(&page.derived_tags)
+            .flat_map(|tag| tag::with_ancestors(tag, separator))
+            .collect()
+    }
+
+    /// Sorted `indexed_tags`, what `lookup_exact` can tell pages apart
+    /// by
+    fn tag_set_key(&self, page: &Page) -> Vec<String> {
+        let mut tags: Vec<String> = self
+            .indexed_tags(page)
+            .into_iter()
+            .map(|tag| tag.to_owned())
+            .collect();
+        tags.sort();
+        tags
+    }
+
+    fn index_page(&mut self, page_id: PageId, page: &Page) {
+        for tag in self.indexed_tags(page) {
+            self.tag_sets
+                .entry(tag.to_owned())
+                .or_insert(Default::default())
+                .insert(page_id);
+        }
+        self.text_index.insert(page_id, page);
+        for tag in page.links.iter().flat_map(|link| &link.tags) {
+            self.links_by_tag
+                .entry(tag.to_owned())
+                .or_insert(Default::default())
+                .insert(page_id);
+        }
+
+        let key = self.tag_set_key(page);
+        let same_tags = self
+            .pages_by_tag_set
+            .entry(key.clone())
+            .or_insert(Default::default());
+        same_tags.insert(page_id);
+        if same_tags.len() > 1 {
+            warn!(
+                "{} pages share the same tags: {}",
+                same_tags.len(),
+                key.join("/")
+            );
+        }
+    }
+
+    fn unindex_page(&mut self, page_id: PageId, page: &Page) {
+        for tag in self.indexed_tags(page) {
+            self.tag_sets.get_mut(tag).unwrap().remove(&page_id);
+        }
+        self.text_index.remove(page_id, page);
+        for tag in page.links.iter().flat_map(|link| &link.tags) {
+            let now_empty = match self.links_by_tag.get_mut(tag) {
+                Some(ids) => {
+                    ids.remove(&page_id);
+                    ids.is_empty()
+                }
+                None => false,
+            };
+            if now_empty {
+                self.links_by_tag.remove(tag);
+            }
+        }
+
+        let key = self.tag_set_key(page);
+        let now_empty = {
+            let same_tags = self.pages_by_tag_set.get_mut(&key).unwrap();
+            same_tags.remove(&page_id);
+            same_tags.is_empty()
+        };
+        if now_empty {
+            self.pages_by_tag_set.remove(&key);
+        }
+    }
+
+    /// Groups of pages having exactly the same tags, counting derived
+    /// tags and ancestors of hierarchical ones
+    ///
+    /// Such pages can't be told apart by their urls.
+    pub fn conflicts(&self) -> Vec<Vec<PageId>> {
+        let mut conflicts: Vec<(&Vec<String>, Vec<PageId>)> = self
+            .pages_by_tag_set
+            .iter()
+            .filter(|(_, ids)| ids.len() > 1)
+            .map(|(tags, ids)| (tags, ids.iter().cloned().collect()))
+            .collect();
+        conflicts.sort_by(|n, m| n.0.cmp(m.0));
+        conflicts.into_iter().map(|(_, ids)| ids).collect()
+    }
+
+    /// Other pages with exactly the same tags as `page_id`
+    pub fn conflicting_pages(&self, page_id: PageId) -> Vec<PageId> {
+        let key = self.tag_set_key(&self.pages_by_id[&page_id]);
+        self.pages_by_tag_set
+            .get(&key)
+            .map(|ids| {
+                ids.iter().cloned().filter(|id| *id != page_id).collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Tags of a `/a/b/c` url path, and whether it prefers an exact
+    /// match
+    ///
+    /// Only literal `/` splits tags; an encoded one (`%2F`) stays
+    /// inside a hierarchical tag. Page urls and links resolve through
+    /// this, so they agree on the page they lead to.
+    pub fn url_tags(&self, path: &str) -> (Vec<String>, bool) {
+        let mut segments: Vec<_> =
+            path.split('/').skip(1).map(tag::normalize).collect();
+
+        let prefer_exact = if segments.last() == Some(&"".into()) {
+            segments.pop();
+            false
+        } else {
+            true
+        };
+
+        (self.join_tag_segments(segments), prefer_exact)
+    }
+
+    /// Join url path segments back into hierarchical tags, when
+    /// the hierarchy separator is `/` too
+    ///
+    /// `lang`, `rust` become `lang/rust` if any page has that tag,
+    /// but only when no page has both `lang` and `rust`: a url that
+    /// works as written keeps its meaning as pages are added.
+    pub fn join_tag_segments(
+        &self,
+        segments: Vec<String>,
+    ) -> Vec<String> {
+        if self.tag_grammar.separator() != Some('/')
+            || !self.pages_with_tags(&segments).is_empty()
+        {
+            return segments;
+        }
+        let is_tag = |tag: &str| {
+            self.tag_sets
+                .get(self.tag_rules.canonical(tag))
+                .map_or(false, |set| !set.is_empty())
+        };
+
+        let mut tags = vec![];
+        let mut start = 0;
+        while start < segments.len() {
+            let end = (start + 2..=segments.len())
+                .rev()
+                .find(|&end| is_tag(&segments[start..end].join("/")))
+                .unwrap_or(start + 1);
+            tags.push(segments[start..end].join("/"));
+            start = end;
+        }
+        tags
+    }
+
+    /// Pages that have all the `tags`
+    pub fn pages_with_tags(&self, tags: &[String]) -> HashSet<PageId> {
+        let mut matches = self.all_pages.clone();
+        for tag in &self.canonical_tags(tags) {
+            match self.tag_sets.get(tag) {
+                Some(set) => {
+                    matches = matches.intersection(set).cloned().collect()
+                }
+                None => return HashSet::new(),
+            }
+        }
+        matches
+    }
+
+    pub fn search_text(
+        &self,
+        query: &search::TextQuery,
+    ) -> Vec<search::Hit> {
+        if query.tags.is_empty() {
+            self.text_index.search(&query.terms, None)
+        } else {
+            let candidates = self.pages_with_tags(&query.tags);
+            self.text_index.search(&query.terms, Some(&candidates))
+        }
+    }
+
     pub fn lookup(&self, tags: Vec<String>) -> Result<PageId> {
         match self.find_best_match(tags, true).type_ {
             MatchType::Many(_) => {
@@ -179,6 +574,7 @@ impl State {
         &self,
         tags: Vec<String>,
     ) -> LookupOutcome {
+        let tags = self.canonical_tags(&tags);
         let mut matches: Option<HashSet<PageId>> = None;
         for tag in tags.iter().cloned() {
             if let Some(set) = self.tag_sets.get(&tag) {
@@ -214,6 +610,7 @@ impl State {
         tags: Vec<String>,
         prefer_exact: bool,
     ) -> Match {
+        let tags = self.canonical_tags(&tags);
         let mut matches: Option<HashSet<PageId>> = None;
         let mut matching_tags = vec![];
         let mut unmatched_tags = vec![];
@@ -254,19 +651,8 @@ impl State {
             .cloned()
             .collect();
 
-        let mut narrowing_tags = HashMap::new();
-
-        for page_id in &matches {
-            for tag in
-                &self.pages_by_id.get(&page_id).unwrap().tags
-            {
-                if !matching_tags.contains(&tag) {
-                    *narrowing_tags
-                        .entry(tag.clone())
-                        .or_insert(0) += 1;
-                }
-            }
-        }
+        let narrowing_tags =
+            self.narrowing_tags(&matches, &matching_tags);
 
         Match {
             unmatched_tags: unmatched_tags,
@@ -306,11 +692,131 @@ impl State {
             },
         }
     }
+
+    /// Pages linking to `page_id`, sorted by title
+    ///
+    /// Links are resolved through `url_tags` like `get` resolves
+    /// urls, so a change to any page can change their targets. Only
+    /// pages with links sharing a tag with `page_id` are indexed, and
+    /// resolved here.
+    pub fn backlinks(&self, page_id: PageId) -> Vec<&Page> {
+        let tags = self.indexed_tags(&self.pages_by_id[&page_id]);
+        let links_here = |link: &Link| {
+            let shares_tag =
+                link.tags.iter().any(|tag| tags.contains(tag.as_str()));
+            if !shares_tag {
+                return false;
+            }
+            let (link_tags, _) = self.url_tags(link.path());
+            let match_ = self.find_best_match(link_tags, true);
+            match match_.type_ {
+                MatchType::One(dst_id) => dst_id == page_id,
+                _ => false,
+            }
+        };
+        let sources: HashSet<PageId> = tags
+            .iter()
+            .filter_map(|tag| self.links_by_tag.get(*tag))
+            .flat_map(|ids| ids.iter().cloned())
+            .filter(|src_id| *src_id != page_id)
+            .collect();
+        let mut pages: Vec<&Page> = sources
+            .into_iter()
+            .map(|src_id| &self.pages_by_id[&src_id])
+            .filter(|page| page.links.iter().any(&links_here))
+            .collect();
+        pages.sort_by(|n, m| n.title.cmp(&m.title));
+        pages
+    }
+
+    fn narrowing_tags(
+        &self,
+        matches: &[PageId],
+        matching_tags: &[String],
+    ) -> NarrowingTagsSet {
+        let separator = self.tag_grammar.separator();
+        // ancestors of matching tags don't narrow anything down
+        let matching_tags: HashSet<&str> = matching_tags
+            .iter()
+            .flat_map(|tag| tag::with_ancestors(tag, separator))
+            .collect();
+        let mut narrowing_tags = HashMap::new();
+
+        for page_id in matches {
+            let page = self.pages_by_id.get(&page_id).unwrap();
+            for tag in self.indexed_tags(page) {
+                if !matching_tags.contains(tag) {
+                    *narrowing_tags
+                        .entry(tag.to_owned())
+                        .or_insert(0) += 1;
+                }
+            }
+        }
+        narrowing_tags
+    }
+
+    pub fn eval_query(&self, query: &Query) -> HashSet<PageId> {
+        match query {
+            Query::Tag(tag) => self
+                .tag_sets
+                .get(self.tag_rules.canonical(tag))
+                .cloned()
+                .unwrap_or_default(),
+            Query::Not(query) => self
+                .all_pages
+                .difference(&self.eval_query(query))
+                .cloned()
+                .collect(),
+            Query::And(queries) => {
+                let mut matches = self.all_pages.clone();
+                for query in queries {
+                    matches = matches
+                        .intersection(&self.eval_query(query))
+                        .cloned()
+                        .collect();
+                }
+                matches
+            }
+            Query::Or(queries) => {
+                let mut matches = HashSet::new();
+                for query in queries {
+                    matches.extend(self.eval_query(query));
+                }
+                matches
+            }
+        }
+    }
+
+    /// Like `find_best_match`, but for a boolean query
+    ///
+    /// Queries are never broadened, so `unmatched_tags` is always empty.
+    pub fn find_query_match(&self, query: &Query) -> Match {
+        let matching_tags = query.positive_tags();
+        let matches: Vec<PageId> =
+            self.eval_query(query).into_iter().take(1000).collect();
+        let narrowing_tags =
+            self.narrowing_tags(&matches, &matching_tags);
+
+        Match {
+            unmatched_tags: vec![],
+            matching_tags: matching_tags,
+            narrowing_tags,
+            type_: match matches.len() {
+                0 => MatchType::None,
+                1 => MatchType::One(matches[0]),
+                _ => MatchType::Many(matches),
+            },
+        }
+    }
 }
 
 #[derive(Clone)]
 pub struct SyncState {
     inner: sync::Arc<sync::RwLock<State>>,
+    /// Held from reading a page file until replacing it
+    edit_lock: sync::Arc<sync::Mutex<()>>,
+    history: Option<History>,
+    git: Option<GitRepo>,
 }
 
 impl SyncState {
@@ -319,6 +825,62 @@ impl SyncState {
             inner: sync::Arc::new(sync::RwLock::new(
                 State::new(),
             )),
+            edit_lock: Default::default(),
+            history: None,
+            git: None,
+        }
+    }
+
+    /// Keep other changes made through the wiki out until the guard
+    /// is dropped, so a page file can be checked and then replaced
+    pub fn lock_edits(&self) -> sync::MutexGuard<()> {
+        self.edit_lock.lock().unwrap()
+    }
+
+    /// Keep previous versions of changed pages in `history`
+    pub fn set_history(&mut self, history: History) {
+        self.history = Some(history);
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    /// Commit every change made through the wiki to `git`
+    pub fn set_git(&mut self, git: GitRepo) {
+        self.git = Some(git);
+    }
+
+    pub fn git(&self) -> Option<&GitRepo> {
+        self.git.as_ref()
+    }
+
+    /// Commit `paths`, with a message like "Update idea/brainwiki"
+    fn commit_change(&self, paths: &[&Path], verb: &str, page: &Page) {
+        if let Some(ref git) = self.git {
+            let message = format!("{} {}", verb, page.tags.join("/"));
+            let paths: Vec<PathBuf> =
+                paths.iter().map(|path| normalize_path(path)).collect();
+            let paths: Vec<&Path> =
+                paths.iter().map(|path| path.as_path()).collect();
+            if let Err(e) = git.commit(&paths, &message) {
+                warn!("Failed to commit \"{}\": {}", message, e);
+            }
+        }
+    }
+
+    fn save_revision(&self, path: &Path, old_page: &Page, new_md: &str) {
+        if old_page.md == new_md {
+            return;
+        }
+        if let Some(ref history) = self.history {
+            if let Err(e) = history.save(path, &old_page.md) {
+                warn!(
+                    "Failed to save revision of {}: {}",
+                    path.display(),
+                    e
+                );
+            }
         }
     }
     pub fn write<'a>(
@@ -339,7 +901,7 @@ impl SyncState {
     ) -> Result<()> {
         let mut path_text = page.suggested_filename();
 
-        println!("{}", path_text);
+        debug!("Writing new page: {}", path_text);
         let (mut tmp_file, tmp_file_path, dst_path) = loop {
             let dst_path = data_dir
                 .join(path_text.clone())
@@ -368,7 +930,8 @@ impl SyncState {
         tmp_file.flush()?;
         drop(tmp_file);
         fs::rename(tmp_file_path, dst_path.clone())?;
-        let _ = self.handle_create(dst_path)?;
+        let _ = self.handle_create(dst_path.clone())?;
+        self.commit_change(&[&dst_path], "Create", page);
 
         Ok(())
     }
@@ -387,11 +950,96 @@ impl SyncState {
         fs::rename(tmp_file_path.clone(), path)?;
 
         let _ = self.handle_rename(tmp_file_path, path.into())?;
+        self.commit_change(&[path], "Update", page);
+        Ok(())
+    }
+
+    /// Append `#tag` to the page stored at `path`
+    pub fn add_tag(&self, path: &Path, tag: &str) -> Result<Page> {
+        let _edit_lock = self.lock_edits();
+        let tag_md = format!("#{}", tag);
+        let grammar = self.read().tag_grammar().clone();
+        if grammar.find_tags(&tag_md) != vec![tag::normalize(tag)] {
+            bail!("Invalid tag: {}", tag);
+        }
+
+        let md = {
+            let inner = self.read();
+            let page_id = inner
+                .pages_by_path
+                .get(path)
+                .ok_or_else(|| format_err!("Not found"))?;
+            inner.pages_by_id.get(page_id).unwrap().md.clone()
+        };
+        let mut md = md.trim_right().to_owned();
+        md += "\n\n";
+        md += tag_md.as_str();
+        md += "\n";
+
+        let new_page = Page::from_markdown(md, &grammar);
+        self.replace_file(path, &new_page)?;
+        Ok(new_page)
+    }
+
+    /// Move the page file into the trash dir and drop it from the state
+    pub fn move_to_trash(
+        &self,
+        path: &Path,
+        data_dir: &Path,
+    ) -> Result<()> {
+        let trash_dir = trash_dir(data_dir);
+        fs::create_dir_all(&trash_dir)?;
+        let dst_path =
+            free_path_in(&trash_dir, file_stem_str(path)?);
+        let page = self.read_page(path)?;
+        fs::rename(path, &dst_path)?;
+
+        self.handle_remove(path.into())?;
+        self.commit_change(&[path], "Delete", &page);
         Ok(())
     }
 
+    /// Move a trashed file back into the data dir
+    ///
+    /// Returns the url of the restored page.
+    pub fn restore_from_trash(
+        &self,
+        file_name: &str,
+        data_dir: &Path,
+    ) -> Result<String> {
+        if Path::new(file_name).file_name().and_then(|n| n.to_str())
+            != Some(file_name)
+        {
+            bail!("Invalid file name: {}", file_name);
+        }
+        let src_path = trash_dir(data_dir).join(file_name);
+        let page = self.read_page(&src_path)?;
+        if self.read().lookup_exact(page.tags.clone())
+            != LookupOutcome::None
+        {
+            bail!(
+                "Another page already has the tags of {}, \
+                 change its tags first",
+                file_name
+            );
+        }
+
+        let dst_path =
+            free_path_in(data_dir, file_stem_str(&src_path)?);
+        fs::rename(&src_path, &dst_path)?;
+        self.handle_create(dst_path.clone())?;
+        self.commit_change(&[&dst_path], "Restore", &page);
+
+        Ok(page.to_full_url(true))
+    }
+
+    fn read_page(&self, path: &Path) -> Result<Page> {
+        let grammar = self.read().tag_grammar().clone();
+        Page::read_from_file(path, &grammar)
+    }
+
     fn handle_create(&self, path: PathBuf) -> Result<()> {
-        let new_page = Page::read_from_file(&*path)?;
+        let new_page = self.read_page(&path)?;
 
         let mut inner = self.inner.write().unwrap();
         if let Some(id) =
@@ -400,98 +1048,232 @@ impl SyncState {
             inner.remove(id);
         }
         inner.insert(new_page, &path.canonicalize()?);
-
         Ok(())
     }
 
+    /// Remove the page at `path`, or all pages under it, if
+    /// it was a directory
     fn handle_remove(&self, path: PathBuf) -> Result<()> {
+        let path = normalize_path(&path);
         let mut inner = self.inner.write().unwrap();
-        if let Some(id) =
-            inner.pages_by_path.get(path.as_path()).cloned()
-        {
+        let ids: Vec<PageId> = inner
+            .pages_by_path
+            .iter()
+            .filter(|(page_path, _)| page_path.starts_with(&path))
+            .map(|(_, id)| *id)
+            .collect();
+        for id in ids {
             inner.remove(id);
         }
-
         Ok(())
     }
+
     fn handle_rename(
         &self,
         src: PathBuf,
         dst: PathBuf,
     ) -> Result<()> {
-        let new_page = Page::read_from_file(&*dst)?;
+        let new_page = self.read_page(&dst)?;
+        let src = normalize_path(&src);
+        let dst = dst.canonicalize()?;
 
         let mut inner = self.inner.write().unwrap();
         if let Some(id) =
-            inner.pages_by_path.get(dst.as_path()).cloned()
+            inner.pages_by_path.get(src.as_path()).cloned()
         {
             inner.remove(id);
         }
-        if let Some(id) =
-            inner.pages_by_path.get(src.as_path()).cloned()
+        let new_md = new_page.md.clone();
+        let old_page = if let Some(id) =
+            inner.pages_by_path.get(dst.as_path()).cloned()
         {
-            inner.remove(id);
+            Some(inner.update(id, new_page))
+        } else {
+            inner.insert(new_page, &dst);
+            None
+        };
+        drop(inner);
+        if let Some(old_page) = old_page {
+            self.save_revision(&dst, &old_page, &new_md);
         }
-        inner.insert(new_page, &dst.canonicalize()?);
+        Ok(())
+    }
+
+    /// Re-read a page modified in place
+    fn handle_write(&self, path: PathBuf) -> Result<()> {
+        let new_page = self.read_page(&path)?;
+        let path = path.canonicalize()?;
 
+        let mut inner = self.inner.write().unwrap();
+        let new_md = new_page.md.clone();
+        let old_page = if let Some(id) =
+            inner.pages_by_path.get(path.as_path()).cloned()
+        {
+            Some(inner.update(id, new_page))
+        } else {
+            inner.insert(new_page, &path);
+            None
+        };
+        drop(inner);
+        if let Some(old_page) = old_page {
+            self.save_revision(&path, &old_page, &new_md);
+        }
         Ok(())
     }
 }
 
 pub struct FsWatcher {
     // TODO
-    _join_handle: thread::JoinHandle<Result<()>>,
+    _join_handle: thread::JoinHandle<()>,
 }
 
+type WatcherChannel = (
+    RecommendedWatcher,
+    sync::mpsc::Receiver<DebouncedEvent>,
+);
+
 impl FsWatcher {
-    pub fn new(dir: PathBuf, state: SyncState) -> Result<Self> {
+    pub fn new(
+        dir: PathBuf,
+        state: SyncState,
+        filter: PageFilter,
+    ) -> Result<Self> {
+        // first watcher is created eagerly, so setup problems
+        // are reported to the caller
+        let mut channel = Some(Self::watch(&dir)?);
+
+        let join_handle = thread::spawn(move || loop {
+            let (watcher, rx) = match channel.take() {
+                Some(channel) => channel,
+                None => match Self::watch(&dir) {
+                    Ok(channel) => {
+                        info!("Fs watcher re-created");
+                        channel
+                    }
+                    Err(e) => {
+                        state.write().record_error(None, &e);
+                        thread::sleep(WATCHER_RETRY_DELAY);
+                        continue;
+                    }
+                },
+            };
+
+            Self::run(&rx, &state, &filter);
+            drop(watcher);
+            thread::sleep(WATCHER_RETRY_DELAY);
+        });
+
+        Ok(FsWatcher {
+            _join_handle: join_handle,
+        })
+    }
+
+    fn watch(dir: &Path) -> Result<WatcherChannel> {
         let (tx, rx) = sync::mpsc::channel();
         let mut watcher: RecommendedWatcher =
             Watcher::new(tx, Duration::from_millis(10))?;
 
         watcher.watch(dir, RecursiveMode::Recursive)?;
 
-        let join_handle = thread::spawn(move || {
-            let _watcher = watcher;
-            loop {
-                let event = rx.recv().unwrap();
-                println!("{:?}", event);
-                match event {
-                    DebouncedEvent::Create(path) => {
-                        if path
-                            .extension()
-                            .and_then(|e| e.to_str())
-                            == Some("md")
-                        {
-                            let _ = state.handle_create(path)?;
-                        }
+        Ok((watcher, rx))
+    }
+
+    /// Handle events until the underlying watcher fails
+    fn run(
+        rx: &sync::mpsc::Receiver<DebouncedEvent>,
+        state: &SyncState,
+        filter: &PageFilter,
+    ) {
+        loop {
+            let event = match rx.recv() {
+                Ok(event) => event,
+                Err(_) => {
+                    warn!("Fs watcher channel disconnected");
+                    return;
+                }
+            };
+            debug!("{:?}", event);
+
+            if let DebouncedEvent::Error(ref e, None) = event {
+                state
+                    .write()
+                    .record_error(None, &format_err!("{}", e));
+                return;
+            }
+
+            let path = event_path(&event);
+            match Self::handle_event(state, filter, event) {
+                Ok(()) => {
+                    if let Some(path) = path {
+                        state.write().clear_error(&path);
                     }
-                    DebouncedEvent::Remove(path) => {
-                        if path
-                            .extension()
-                            .and_then(|e| e.to_str())
-                            == Some("md")
-                        {
-                            let _ = state.handle_remove(path)?;
-                        }
+                }
+                Err(e) => state.write().record_error(
+                    path.as_ref().map(|p| p.as_path()),
+                    &e,
+                ),
+            }
+        }
+    }
+
+    fn handle_event(
+        state: &SyncState,
+        filter: &PageFilter,
+        event: DebouncedEvent,
+    ) -> Result<()> {
+        match event {
+            DebouncedEvent::Create(path) => {
+                if path.is_dir() {
+                    if !filter.is_ignored(&path, true) {
+                        state.write().insert_from_dir(&path, filter)?;
                     }
-                    DebouncedEvent::Rename(src, dst) => {
-                        if dst
-                            .extension()
-                            .and_then(|e| e.to_str())
-                            == Some("md")
-                        {
-                            state.handle_rename(src, dst)?;
-                        }
+                } else if filter.is_page(&path) {
+                    state.handle_create(path)?;
+                }
+            }
+            DebouncedEvent::NoticeWrite(path)
+            | DebouncedEvent::Write(path)
+            | DebouncedEvent::Chmod(path) => {
+                if filter.is_page(&path) {
+                    state.handle_write(path)?;
+                }
+            }
+            DebouncedEvent::Remove(path) => {
+                state.handle_remove(path)?;
+            }
+            DebouncedEvent::Rename(src, dst) => {
+                if dst.is_dir() {
+                    state.handle_remove(src)?;
+                    if !filter.is_ignored(&dst, true) {
+                        state.write().insert_from_dir(&dst, filter)?;
                     }
-                    _ => {}
+                } else if filter.is_page(&dst) {
+                    state.handle_rename(src, dst)?;
+                } else {
+                    // moved out of the way, eg. into the trash
+                    state.handle_remove(src)?;
                 }
             }
-        });
+            DebouncedEvent::Error(e, _) => {
+                bail!("{}", e);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
 
-        Ok(FsWatcher {
-            _join_handle: join_handle,
-        })
+fn event_path(event: &DebouncedEvent) -> Option<PathBuf> {
+    match event {
+        DebouncedEvent::NoticeWrite(path)
+        | DebouncedEvent::NoticeRemove(path)
+        | DebouncedEvent::Create(path)
+        | DebouncedEvent::Write(path)
+        | DebouncedEvent::Chmod(path)
+        | DebouncedEvent::Remove(path)
+        | DebouncedEvent::Rename(_, path) => Some(path.clone()),
+        DebouncedEvent::Error(_, path) => path.clone(),
+        _ => None,
     }
 }
 
@@ -507,8 +1289,12 @@ fn simple() {
             tags: vec!["a".into(), "b".into()],
             title: "".into(),
             md: "".into(),
+            links: vec![],
+            derived_tags: vec![],
+            created: None,
+            modified: None,
         },
-        Path::new(""),
+        Path::new("p1.md"),
     );
 
     let _p2 = state.insert(
@@ -517,8 +1303,12 @@ fn simple() {
             tags: vec!["a".into(), "c".into()],
             title: "".into(),
             md: "".into(),
+            links: vec![],
+            derived_tags: vec![],
+            created: None,
+            modified: None,
         },
-        Path::new(""),
+        Path::new("p2.md"),
     );
 
     let empty: Vec<String> = vec![];
@@ -572,3 +1362,481 @@ fn simple() {
     );
     assert_eq!(m.unmatched_tags, vec!["x".to_string()]);
 }
+
+#[test]
+fn query_parse() {
+    use crate::query::Query::*;
+
+    let tag = |t: &str| Tag(t.into());
+
+    assert_eq!(Query::parse("a").unwrap(), tag("a"));
+    assert_eq!(
+        Query::parse("project/-done").unwrap(),
+        And(vec![tag("project"), Not(Box::new(tag("done")))])
+    );
+    assert_eq!(
+        Query::parse("(Rust|go)+idea").unwrap(),
+        And(vec![Or(vec![tag("rust"), tag("go")]), tag("idea")])
+    );
+    assert_eq!(
+        Query::parse("a b|c/").unwrap(),
+        Or(vec![And(vec![tag("a"), tag("b")]), tag("c")])
+    );
+    assert_eq!(
+        Query::parse("-(open-source|x)").unwrap(),
+        Not(Box::new(Or(vec![tag("open-source"), tag("x")])))
+    );
+    assert!(Query::parse("(a|b").is_err());
+    assert!(Query::parse("a)").is_err());
+    assert!(Query::parse("a|").is_err());
+
+    assert!(crate::query::is_query("project/-done"));
+    assert!(crate::query::is_query("a+b"));
+    assert!(!crate::query::is_query("open-source/rust"));
+}
+
+#[test]
+fn query_eval() {
+    let mut state: State = Default::default();
+    let mut insert = |tags: &[&str], path: &str| {
+        state.insert(
+            Page::from_markdown(
+                tags.iter().map(|t| format!("#{} ", t)).collect(),
+                &Default::default(),
+            ),
+            Path::new(path),
+        )
+    };
+    let p1 = insert(&["project", "done"], "p1.md");
+    let p2 = insert(&["project", "rust"], "p2.md");
+    let p3 = insert(&["go", "idea"], "p3.md");
+
+    let m = state
+        .find_query_match(&Query::parse("project/-done").unwrap());
+    assert_eq!(m.type_, MatchType::One(p2));
+    assert_eq!(m.matching_tags, vec!["project".to_string()]);
+    assert_eq!(m.narrowing_tags.get("rust"), Some(&1));
+
+    let matches =
+        state.eval_query(&Query::parse("rust|go").unwrap());
+    assert_eq!(matches, [p2, p3].iter().cloned().collect());
+
+    let matches = state.eval_query(&Query::parse("-rust").unwrap());
+    assert_eq!(matches, [p1, p3].iter().cloned().collect());
+}
+
+#[cfg(test)]
+fn filter(dir: &Path) -> PageFilter {
+    PageFilter::load(dir).unwrap()
+}
+
+#[cfg(test)]
+fn wait_for<F: Fn(&State) -> bool>(state: &SyncState, f: F) -> bool {
+    for _ in 0..200 {
+        if f(&*state.read()) {
+            return true;
+        }
+        thread::sleep(Duration::from_millis(25));
+    }
+    false
+}
+
+#[test]
+fn watcher_picks_up_new_and_removed_files() {
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let dir_path = dir.path().canonicalize().unwrap();
+    let state = SyncState::new();
+    let _watcher = FsWatcher::new(
+        dir_path.clone(),
+        state.clone(),
+        filter(&dir_path),
+    ).unwrap();
+
+    let path = dir_path.join("page.md");
+    fs::write(&path, "# Page\n#foo\n").unwrap();
+    assert!(wait_for(&state, |s| {
+        s.lookup_exact(vec!["foo".into()]) != LookupOutcome::None
+    }));
+
+    fs::remove_file(&path).unwrap();
+    assert!(wait_for(&state, |s| {
+        s.lookup_exact(vec!["foo".into()]) == LookupOutcome::None
+    }));
+}
+
+#[test]
+fn watcher_picks_up_in_place_writes() {
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let dir_path = dir.path().canonicalize().unwrap();
+    let path = dir_path.join("page.md");
+    fs::write(&path, "# Page\n#foo\n").unwrap();
+
+    let state = SyncState::new();
+    let _watcher = FsWatcher::new(
+        dir_path.clone(),
+        state.clone(),
+        filter(&dir_path),
+    ).unwrap();
+    state
+        .write()
+        .insert_from_dir(&dir_path, &filter(&dir_path))
+        .unwrap();
+
+    let id = match state.read().lookup_exact(vec!["foo".into()]) {
+        LookupOutcome::One(id) => id,
+        other => panic!("unexpected lookup: {:?}", other),
+    };
+
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    file.write_all(b"# Page\n#bar\n").unwrap();
+    drop(file);
+
+    assert!(wait_for(&state, |s| {
+        s.lookup_exact(vec!["bar".into()]) == LookupOutcome::One(id)
+    }));
+    assert_eq!(
+        state.read().lookup_exact(vec!["foo".into()]),
+        LookupOutcome::None
+    );
+}
+
+#[test]
+fn watcher_survives_unreadable_files() {
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let dir_path = dir.path().canonicalize().unwrap();
+    let state = SyncState::new();
+    let _watcher = FsWatcher::new(
+        dir_path.clone(),
+        state.clone(),
+        filter(&dir_path),
+    ).unwrap();
+
+    let bad_path = dir_path.join("bad.md");
+    fs::write(&bad_path, b"# Bad\n#bad \xff\xfe\n").unwrap();
+    assert!(wait_for(&state, |s| {
+        s.load_status.failed_files.contains_key(&bad_path)
+    }));
+
+    fs::write(dir_path.join("good.md"), "# Good\n#good\n").unwrap();
+    assert!(wait_for(&state, |s| {
+        s.lookup_exact(vec!["good".into()]) != LookupOutcome::None
+    }));
+
+    fs::remove_file(&bad_path).unwrap();
+    assert!(wait_for(&state, |s| {
+        s.load_status.failed_files.is_empty()
+    }));
+}
+
+#[test]
+fn insert_from_dir_is_recursive() {
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let root = dir.path();
+    fs::create_dir_all(root.join("journal/2018")).unwrap();
+    fs::create_dir_all(root.join(".git")).unwrap();
+    fs::write(root.join("top.md"), "#top").unwrap();
+    fs::write(root.join("journal/2018/day.md"), "#day").unwrap();
+    fs::write(root.join(".git/skipped.md"), "#skipped").unwrap();
+
+    let mut state = State::new();
+    state.insert_from_dir(root, &filter(root)).unwrap();
+
+    assert!(state.lookup_exact(vec!["top".into()]) != LookupOutcome::None);
+    assert!(state.lookup_exact(vec!["day".into()]) != LookupOutcome::None);
+    assert_eq!(
+        state.lookup_exact(vec!["skipped".into()]),
+        LookupOutcome::None
+    );
+}
+
+#[test]
+fn backlinks() {
+    let mut state: State = Default::default();
+    let target = state.insert(
+        Page::from_markdown(
+            "# Target\n#idea #brainwiki".into(),
+            &Default::default(),
+        ),
+        Path::new("target.md"),
+    );
+    let other = state.insert(
+        Page::from_markdown(
+            "# Other\n#idea #other".into(),
+            &Default::default(),
+        ),
+        Path::new("other.md"),
+    );
+    let source = state.insert(
+        Page::from_markdown(
+            "# Source\n#src [x](/idea/brainwiki) [y](/idea/)".into(),
+            &Default::default(),
+        ),
+        Path::new("source.md"),
+    );
+
+    let titles = |state: &State| -> Vec<String> {
+        state
+            .backlinks(target)
+            .iter()
+            .map(|p| p.title.clone())
+            .collect()
+    };
+    assert_eq!(titles(&state), vec!["Source".to_string()]);
+
+    // `/idea/` is ambiguous until the other `#idea` page is gone
+    state.insert(
+        Page::from_markdown(
+            "# Vague\n#vague [y](/idea/)".into(),
+            &Default::default(),
+        ),
+        Path::new("vague.md"),
+    );
+    assert_eq!(titles(&state), vec!["Source".to_string()]);
+    state.remove(other);
+    assert_eq!(
+        titles(&state),
+        vec!["Source".to_string(), "Vague".to_string()]
+    );
+
+    state.remove(source);
+    assert_eq!(titles(&state), vec!["Vague".to_string()]);
+}
+
+#[test]
+fn hierarchical_tags() {
+    use crate::settings::TagSettings;
+
+    let mut state: State = Default::default();
+    state.set_tag_grammar(
+        Grammar::new(&TagSettings {
+            hierarchy_separator: Some('/'),
+            ..TagSettings::default()
+        })
+        .unwrap(),
+    );
+    let page =
+        |md: &str| Page::from_markdown(md.into(), state.tag_grammar());
+    let rust = page("# Rust\n#lang/rust #idea");
+    let go = page("# Go\n#lang/go");
+    let flat = page("# Flat\n#lang #rust");
+    let rust = state.insert(rust, Path::new("rust.md"));
+    let go = state.insert(go, Path::new("go.md"));
+
+    assert_eq!(state.pages_with_tags(&["lang".into()]).len(), 2);
+    let match_ = state.find_best_match(vec!["lang".into()], false);
+    let mut narrowing: Vec<_> =
+        match_.narrowing_tags.into_iter().collect();
+    narrowing.sort();
+    assert_eq!(
+        narrowing,
+        vec![
+            ("idea".into(), 1),
+            ("lang/go".into(), 1),
+            ("lang/rust".into(), 1)
+        ]
+    );
+
+    assert_eq!(
+        state.lookup_exact(vec!["lang/rust".into()]),
+        LookupOutcome::One(rust)
+    );
+    state.remove(go);
+    assert_eq!(
+        state.lookup_exact(vec!["lang".into()]),
+        LookupOutcome::One(rust)
+    );
+
+    let segments = || vec!["lang".to_string(), "rust".to_string()];
+    assert_eq!(state.join_tag_segments(segments()), vec!["lang/rust"]);
+    state.insert(flat, Path::new("flat.md"));
+    assert_eq!(state.join_tag_segments(segments()), segments());
+}
+
+#[test]
+fn tag_rules() {
+    let mut state: State = Default::default();
+    state.set_tag_rules(
+        tag::Rules::parse(
+            "[aliases]\njs = \"javascript\"\n\
+             [implies]\njavascript = [\"programming\"]",
+        )
+        .unwrap(),
+    );
+    let page = state.insert(
+        Page::from_markdown(
+            "# Page\n#js #idea [x](/js/)".into(),
+            &Default::default(),
+        ),
+        Path::new("page.md"),
+    );
+
+    let inserted = &state.pages_by_id[&page];
+    assert_eq!(inserted.tags, vec!["idea", "javascript"]);
+    assert_eq!(inserted.derived_tags, vec!["programming"]);
+    assert_eq!(inserted.links[0].tags, vec!["javascript"]);
+
+    let match_ = state.find_best_match(vec!["js".into()], true);
+    assert_eq!(match_.matching_tags, vec!["javascript"]);
+    assert_eq!(match_.type_, MatchType::One(page));
+    assert_eq!(
+        state.lookup_exact(vec!["programming".into()]),
+        LookupOutcome::One(page)
+    );
+    assert_eq!(
+        state.eval_query(&Query::parse("js+programming").unwrap()),
+        [page].iter().cloned().collect()
+    );
+}
+
+#[test]
+fn tag_set_conflicts() {
+    let mut state: State = Default::default();
+    let p1 = state.insert(
+        Page::from_markdown(
+            "# One\n#a #b".into(),
+            &Default::default(),
+        ),
+        Path::new("p1.md"),
+    );
+    let p2 = state.insert(
+        Page::from_markdown(
+            "# Two\n#b #a".into(),
+            &Default::default(),
+        ),
+        Path::new("p2.md"),
+    );
+    let p3 = state.insert(
+        Page::from_markdown(
+            "# Three\n#a".into(),
+            &Default::default(),
+        ),
+        Path::new("p3.md"),
+    );
+
+    let conflicts = state.conflicts();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(
+        conflicts[0].iter().cloned().collect::<HashSet<_>>(),
+        [p1, p2].iter().cloned().collect()
+    );
+    assert_eq!(state.conflicting_pages(p1), vec![p2]);
+    assert!(state.conflicting_pages(p3).is_empty());
+
+    state.update(
+        p2,
+        Page::from_markdown(
+            "# Two\n#b #a #c".into(),
+            &Default::default(),
+        ),
+    );
+    assert!(state.conflicts().is_empty());
+    assert!(state.conflicting_pages(p1).is_empty());
+}
+
+#[test]
+fn tag_set_conflicts_with_derived_tags() {
+    let mut state: State = Default::default();
+    state.set_tag_rules(
+        tag::Rules::parse("[implies]\nrust = [\"programming\"]")
+            .unwrap(),
+    );
+    let page =
+        |md: &str| Page::from_markdown(md.into(), &Default::default());
+    let implied = state.insert(page("# A\n#rust"), Path::new("a.md"));
+    let written = state
+        .insert(page("# B\n#rust #programming"), Path::new("b.md"));
+
+    assert_eq!(
+        state.lookup_exact(vec!["rust".into()]),
+        LookupOutcome::Many
+    );
+    assert_eq!(state.conflicts().len(), 1);
+    assert_eq!(state.conflicting_pages(implied), vec![written]);
+}
+
+#[test]
+fn replace_file_keeps_history() {
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let dir_path = dir.path().canonicalize().unwrap();
+    let path = dir_path.join("page.md");
+    fs::write(&path, "# Page\n#foo\nfirst").unwrap();
+
+    let mut state = SyncState::new();
+    state.set_history(History::new(&dir_path).unwrap());
+    state
+        .write()
+        .insert_from_dir(&dir_path, &filter(&dir_path))
+        .unwrap();
+
+    state
+        .replace_file(
+            &path,
+            &Page::from_markdown(
+                "# Page\n#foo\nsecond".into(),
+                &Default::default(),
+            ),
+        )
+        .unwrap();
+
+    let history = state.history().unwrap();
+    let revisions = history.revisions(&path).unwrap();
+    assert_eq!(revisions.len(), 1);
+    assert_eq!(
+        history.read(&path, &revisions[0].id).unwrap(),
+        "# Page\n#foo\nfirst"
+    );
+}
+
+#[test]
+fn trash_restore() {
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let dir_path = dir.path().canonicalize().unwrap();
+    let path = dir_path.join("page.md");
+    fs::write(&path, "# Page\n#foo").unwrap();
+
+    let state = SyncState::new();
+    state
+        .write()
+        .insert_from_dir(&dir_path, &filter(&dir_path))
+        .unwrap();
+    state.move_to_trash(&path, &dir_path).unwrap();
+    fs::write(trash_dir(&dir_path).join("broken.md"), [0xff, 0xfe])
+        .unwrap();
+
+    let trashed = list_trash(&dir_path, &Default::default()).unwrap();
+    assert_eq!(trashed.len(), 1);
+    assert_eq!(trashed[0].file_name, "page.md");
+
+    fs::write(dir_path.join("other.md"), "# Other\n#foo").unwrap();
+    state.handle_create(dir_path.join("other.md")).unwrap();
+    assert!(state.restore_from_trash("page.md", &dir_path).is_err());
+    let trashed = list_trash(&dir_path, &Default::default()).unwrap();
+    assert_eq!(trashed.len(), 1);
+}
+
+#[test]
+fn add_tag_checks_the_tag() {
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let dir_path = dir.path().canonicalize().unwrap();
+    let path = dir_path.join("page.md");
+    fs::write(&path, "# Page\n#foo").unwrap();
+
+    let state = SyncState::new();
+    state
+        .write()
+        .insert_from_dir(&dir_path, &filter(&dir_path))
+        .unwrap();
+
+    assert!(state.add_tag(&path, "a b").is_err());
+    assert!(state.add_tag(&path, "a#b").is_err());
+    let page = state.add_tag(&path, "Bar").unwrap();
+    assert_eq!(page.tags, ["bar", "foo"]);
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        "# Page\n#foo\n\n#Bar\n"
+    );
+}
@@ -0,0 +1,117 @@
+//! Atom feeds of pages matching a tag query
+
+use chrono::{DateTime, Local};
+
+use crate::{page::Page, search::escape_html};
+
+/// Feed header
+pub struct Feed<'a> {
+    pub title: &'a str,
+    pub author: &'a str,
+    /// Like `https://wiki.example.com`, without trailing slash
+    pub base_url: &'a str,
+    /// Absolute url of the feed itself
+    pub feed_url: &'a str,
+}
+
+fn updated(page: &Page) -> Option<DateTime<Local>> {
+    page.modified.or(page.created)
+}
+
+/// Sort `pages` for a feed: most recently modified first
+pub fn sort_pages(pages: &mut Vec<Page>) {
+    pages.sort_by(|n, m| updated(m).cmp(&updated(n)));
+}
+
+fn entry(feed: &Feed, page: &Page) -> String {
+    let url = format!("{}{}", feed.base_url, page.to_full_url(true));
+    let updated = updated(page).unwrap_or_else(Local::now);
+    format!(
+        concat!(
+            "<entry>",
+            "<id>{url}</id>",
+            "<title>{title}</title>",
+            "<link href=\"{url}\"/>",
+            "<updated>{updated}</updated>",
+            "<content type=\"html\">{content}</content>",
+            "</entry>\n"
+        ),
+        url = escape_html(&url),
+        title = escape_html(&page.title),
+        updated = updated.to_rfc3339(),
+        content = escape_html(&page.html),
+    )
+}
+
+/// Atom xml of `pages`, in the given order
+pub fn atom(feed: &Feed, pages: &[Page]) -> String {
+    let updated = pages
+        .iter()
+        .filter_map(updated)
+        .max()
+        .unwrap_or_else(Local::now);
+
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    xml.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    xml += &format!(
+        concat!(
+            "<id>{feed_url}</id>\n",
+            "<title>{title}</title>\n",
+            "<link rel=\"self\" href=\"{feed_url}\"/>\n",
+            "<link href=\"{base_url}/\"/>\n",
+            "<updated>{updated}</updated>\n",
+            "<author><name>{author}</name></author>\n"
+        ),
+        feed_url = escape_html(feed.feed_url),
+        base_url = escape_html(feed.base_url),
+        title = escape_html(feed.title),
+        updated = updated.to_rfc3339(),
+        author = escape_html(feed.author),
+    );
+    for page in pages {
+        xml.push_str(&entry(feed, page));
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+#[test]
+fn entries() {
+    use chrono::TimeZone;
+
+    let mut old = Page::from_markdown(
+        "# Old\n#journal".into(),
+        &Default::default(),
+    );
+    old.modified = Some(Local.ymd(2018, 7, 1).and_hms(10, 0, 0));
+    let mut new = Page::from_markdown(
+        "# New & improved\n#journal #rust".into(),
+        &Default::default(),
+    );
+    new.modified = Some(Local.ymd(2018, 7, 2).and_hms(10, 0, 0));
+
+    let mut pages = vec![old, new];
+    sort_pages(&mut pages);
+    let xml = atom(
+        &Feed {
+            title: "Wiki: journal",
+            author: "me",
+            base_url: "https://wiki.example.com",
+            feed_url: "https://wiki.example.com/~feed/journal",
+        },
+        &pages,
+    );
+
+    let new_pos = xml.find("<title>New &amp; improved</title>").unwrap();
+    let old_pos = xml.find("<title>Old</title>").unwrap();
+    assert!(new_pos < old_pos);
+    assert!(xml.contains(
+        "<link href=\"https://wiki.example.com/journal/rust\"/>"
+    ));
+    assert!(xml.contains(&format!(
+        "<updated>{}</updated>\n",
+        Local.ymd(2018, 7, 2).and_hms(10, 0, 0).to_rfc3339()
+    )));
+    assert!(xml.contains("<author><name>me</name></author>"));
+}
@@ -0,0 +1,206 @@
+//! Committing page changes to a git repository containing the data dir
+use chrono::{Local, TimeZone};
+use git2::{
+    build::TreeUpdateBuilder, Commit, FileMode, Oid, Repository,
+    Signature,
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use crate::{history::Revision, Result};
+
+#[derive(Clone)]
+pub struct GitRepo {
+    data_dir: PathBuf,
+    /// Serializes commits, so they don't race for the index lock
+    lock: Arc<Mutex<()>>,
+}
+
+impl GitRepo {
+    /// Use the repository the data dir is in
+    pub fn open(data_dir: &Path) -> Result<Self> {
+        // fail early if there's no repository at all
+        let _ = Repository::discover(data_dir)?;
+        Ok(GitRepo {
+            data_dir: data_dir.canonicalize()?,
+            lock: Arc::new(Mutex::new(())),
+        })
+    }
+
+    fn repo(&self) -> Result<(Repository, PathBuf)> {
+        let repo = Repository::discover(&self.data_dir)?;
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| format_err!("Bare git repositories are not supported"))?
+            .canonicalize()?;
+        Ok((repo, workdir))
+    }
+
+    /// Commit the current content of `paths`
+    ///
+    /// Paths that don't exist anymore are removed from the repository.
+    /// Only `paths` change compared to `HEAD`, anything else staged in
+    /// the repository stays staged.
+    pub fn commit(&self, paths: &[&Path], message: &str) -> Result<Oid> {
+        let _lock = self.lock.lock().unwrap();
+        let (repo, workdir) = self.repo()?;
+
+        let parent = match repo.head() {
+            Ok(head) => Some(head.peel_to_commit()?),
+            Err(_) => None,
+        };
+        let base_tree = match parent {
+            Some(ref parent) => parent.tree()?,
+            None => repo.find_tree(repo.treebuilder(None)?.write()?)?,
+        };
+
+        let mut index = repo.index()?;
+        let mut update = TreeUpdateBuilder::new();
+        for path in paths {
+            let rel_path = path.strip_prefix(&workdir)?;
+            if path.exists() {
+                index.add_path(rel_path)?;
+                let blob_id = repo.blob_path(path)?;
+                update.upsert(rel_path, blob_id, FileMode::Blob);
+            } else {
+                index.remove_path(rel_path)?;
+                update.remove(rel_path);
+            }
+        }
+        // keep `git status` clean for the committed paths
+        index.write()?;
+        let tree =
+            repo.find_tree(update.create_updated(&repo, &base_tree)?)?;
+
+        let signature = repo
+            .signature()
+            .or_else(|_| Signature::now("BrainWiki", "brainwiki@localhost"))?;
+        let parents: Vec<&Commit> = parent.iter().collect();
+
+        Ok(repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            message,
+            &tree,
+            &parents,
+        )?)
+    }
+
+    fn blob_id(commit: &Commit, rel_path: &Path) -> Option<Oid> {
+        commit
+            .tree()
+            .ok()
+            .and_then(|tree| tree.get_path(rel_path).ok())
+            .map(|entry| entry.id())
+    }
+
+    /// Up to `limit` latest commits that changed the file at `path`,
+    /// newest first
+    pub fn log(&self, path: &Path, limit: usize) -> Result<Vec<Revision>> {
+        let (repo, workdir) = self.repo()?;
+        let rel_path = path.strip_prefix(&workdir)?;
+
+        let mut revwalk = repo.revwalk()?;
+        if revwalk.push_head().is_err() {
+            // no commits yet
+            return Ok(vec![]);
+        }
+
+        let mut revisions = vec![];
+        for id in revwalk {
+            if revisions.len() >= limit {
+                break;
+            }
+            let commit = repo.find_commit(id?)?;
+            let blob_id = Self::blob_id(&commit, rel_path);
+            if blob_id.is_none() {
+                continue;
+            }
+            let parent_blob_id = commit
+                .parents()
+                .next()
+                .and_then(|parent| Self::blob_id(&parent, rel_path));
+            if blob_id == parent_blob_id {
+                continue;
+            }
+
+            revisions.push(Revision {
+                id: commit.id().to_string(),
+                time: Local.timestamp(commit.time().seconds(), 0),
+            });
+        }
+        Ok(revisions)
+    }
+
+    /// Content of the file at `path` as of commit `id`
+    pub fn read(&self, path: &Path, id: &str) -> Result<String> {
+        let (repo, workdir) = self.repo()?;
+        let rel_path = path.strip_prefix(&workdir)?;
+
+        let commit = repo.find_commit(Oid::from_str(id)?)?;
+        let blob_id = Self::blob_id(&commit, rel_path)
+            .ok_or_else(|| format_err!("File not found in {}", id))?;
+        let blob = repo.find_blob(blob_id)?;
+
+        Ok(String::from_utf8(blob.content().to_owned())?)
+    }
+}
+
+#[test]
+fn commit_and_log() {
+    use std::fs;
+
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    Repository::init(dir.path()).unwrap();
+    let git = GitRepo::open(dir.path()).unwrap();
+    let path = dir.path().canonicalize().unwrap().join("a.md");
+    let other_path = dir.path().canonicalize().unwrap().join("b.md");
+
+    assert!(git.log(&path, 10).unwrap().is_empty());
+
+    fs::write(&path, "first").unwrap();
+    let first = git.commit(&[&path], "Create a").unwrap();
+    fs::write(&other_path, "other").unwrap();
+    git.commit(&[&other_path], "Create b").unwrap();
+    fs::write(&path, "second").unwrap();
+    let second = git.commit(&[&path], "Update a").unwrap();
+
+    let log = git.log(&path, 10).unwrap();
+    assert_eq!(
+        log.iter().map(|r| r.id.clone()).collect::<Vec<_>>(),
+        vec![second.to_string(), first.to_string()]
+    );
+    assert_eq!(git.read(&path, &first.to_string()).unwrap(), "first");
+    assert_eq!(git.log(&path, 1).unwrap()[0].id, second.to_string());
+
+    fs::remove_file(&path).unwrap();
+    git.commit(&[&path], "Delete a").unwrap();
+    assert!(git.log(&other_path, 10).unwrap().len() == 1);
+}
+
+#[test]
+fn commit_leaves_other_staged_files() {
+    use std::fs;
+
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    let git = GitRepo::open(dir.path()).unwrap();
+    let root = dir.path().canonicalize().unwrap();
+
+    fs::write(root.join("staged.md"), "staged").unwrap();
+    let mut index = repo.index().unwrap();
+    index.add_path(Path::new("staged.md")).unwrap();
+    index.write().unwrap();
+
+    fs::write(root.join("a.md"), "a").unwrap();
+    let id = git.commit(&[&root.join("a.md")], "Create a").unwrap();
+
+    let tree = repo.find_commit(id).unwrap().tree().unwrap();
+    assert!(tree.get_path(Path::new("a.md")).is_ok());
+    assert!(tree.get_path(Path::new("staged.md")).is_err());
+    let index = repo.index().unwrap();
+    assert!(index.get_path(Path::new("staged.md"), 0).is_some());
+}
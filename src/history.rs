@@ -0,0 +1,131 @@
+//! Previous versions of pages
+//!
+//! Every time a page changes, its previous content is kept in
+//! `<data-dir>/.history/<path-of-the-page>/<timestamp>.md`.
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone, Utc};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::Result;
+
+/// Name of the directory (inside data dir) keeping old revisions
+pub const HISTORY_DIR_NAME: &str = ".history";
+
+const REVISION_ID_FORMAT: &str = "%Y%m%d-%H%M%S%.6f";
+
+#[derive(Debug, Clone)]
+pub struct Revision {
+    pub id: String,
+    pub time: DateTime<Local>,
+}
+
+#[derive(Debug, Clone)]
+pub struct History {
+    data_dir: PathBuf,
+}
+
+fn revision_path(dir: &Path, id: &str) -> PathBuf {
+    dir.join(format!("{}.md", id))
+}
+
+fn is_valid_revision_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.chars().all(|c| c.is_ascii_digit() || c == '-' || c == '.' || c == '_')
+}
+
+impl History {
+    pub fn new(data_dir: &Path) -> Result<Self> {
+        Ok(History {
+            data_dir: data_dir.canonicalize()?,
+        })
+    }
+
+    fn page_dir(&self, page_path: &Path) -> Result<PathBuf> {
+        let rel_path = page_path.strip_prefix(&self.data_dir).map_err(|_| {
+            format_err!("{} is not in the data dir", page_path.display())
+        })?;
+        Ok(self.data_dir.join(HISTORY_DIR_NAME).join(rel_path))
+    }
+
+    /// Keep `md` as a previous version of the page at `page_path`
+    pub fn save(&self, page_path: &Path, md: &str) -> Result<Revision> {
+        let dir = self.page_dir(page_path)?;
+        fs::create_dir_all(&dir)?;
+
+        let now = Utc::now();
+        let mut id = now.format(REVISION_ID_FORMAT).to_string();
+        while revision_path(&dir, &id).exists() {
+            id += "_";
+        }
+        fs::write(revision_path(&dir, &id), md)?;
+
+        Ok(Revision {
+            id: id,
+            time: now.with_timezone(&Local),
+        })
+    }
+
+    /// Revisions of the page at `page_path`, newest first
+    pub fn revisions(&self, page_path: &Path) -> Result<Vec<Revision>> {
+        let dir = self.page_dir(page_path)?;
+        let mut revisions = vec![];
+        if !dir.exists() {
+            return Ok(revisions);
+        }
+
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let id = match path.file_stem().and_then(|s| s.to_str()) {
+                Some(id) if is_valid_revision_id(id) => id.to_owned(),
+                _ => continue,
+            };
+            // not every digit-only name is a revision
+            let time = match NaiveDateTime::parse_from_str(
+                id.trim_right_matches('_'),
+                REVISION_ID_FORMAT,
+            ) {
+                Ok(time) => time,
+                Err(_) => continue,
+            };
+            revisions.push(Revision {
+                id: id,
+                time: Utc.from_utc_datetime(&time).with_timezone(&Local),
+            });
+        }
+        revisions.sort_by(|n, m| m.id.cmp(&n.id));
+        Ok(revisions)
+    }
+
+    pub fn read(&self, page_path: &Path, id: &str) -> Result<String> {
+        if !is_valid_revision_id(id) {
+            bail!("Invalid revision: {}", id);
+        }
+        let path = revision_path(&self.page_dir(page_path)?, id);
+        Ok(fs::read_to_string(path)?)
+    }
+}
+
+#[test]
+fn save_and_read() {
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let history = History::new(dir.path()).unwrap();
+    let page_path = dir.path().canonicalize().unwrap().join("work/a.md");
+
+    assert!(history.revisions(&page_path).unwrap().is_empty());
+
+    let first = history.save(&page_path, "first").unwrap();
+    let second = history.save(&page_path, "second").unwrap();
+
+    let revisions = history.revisions(&page_path).unwrap();
+    assert_eq!(revisions.len(), 2);
+    assert_eq!(revisions[0].id, second.id);
+    assert_eq!(revisions[1].id, first.id);
+    assert_eq!(history.read(&page_path, &first.id).unwrap(), "first");
+
+    let stray = history.page_dir(&page_path).unwrap().join("1.md");
+    fs::write(stray, "stray").unwrap();
+    assert_eq!(history.revisions(&page_path).unwrap().len(), 2);
+    assert!(history.read(&page_path, "../../a").is_err());
+}
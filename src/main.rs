@@ -62,11 +62,26 @@ extern crate boolinator;
 #[macro_use]
 extern crate quicli;
 
+mod api;
+mod audit;
+mod auth;
+mod check;
 mod data;
+mod feed;
+mod git;
+mod history;
 mod markdown;
+mod merge;
 mod opts;
 mod page;
+mod page_filter;
+mod query;
+mod rename;
+mod retag;
+mod search;
 mod settings;
+mod tag;
+mod throttle;
 mod tpl;
 mod util;
 mod web;
@@ -102,14 +117,114 @@ main!(|opts: opts::Opts| {
         return Ok(());
     }
 
-    let state = data::SyncState::new();
+    if let Some(opts::Command::Token(ref cmd)) = opts.command {
+        match cmd {
+            opts::TokenCommand::Create { name, scope } => {
+                let token =
+                    settings.create_token(name.clone(), *scope)?;
+                settings.write_to_dir(&opts.data_dir)?;
+                println!("{}", token);
+            }
+            opts::TokenCommand::List => {
+                for token in &settings.api_tokens {
+                    println!("{}: {}", token.name, token.scope);
+                }
+            }
+            opts::TokenCommand::Revoke { name } => {
+                settings.revoke_token(name)?;
+                settings.write_to_dir(&opts.data_dir)?;
+            }
+        }
+        return Ok(());
+    }
+
+    let mut state = data::SyncState::new();
+    state
+        .write()
+        .set_tag_grammar(tag::Grammar::new(&settings.tags)?);
+    state
+        .write()
+        .set_tag_rules(tag::Rules::load(&opts.data_dir)?);
+    state.set_history(history::History::new(&opts.data_dir)?);
+    if opts.git {
+        state.set_git(git::GitRepo::open(&opts.data_dir)?);
+    }
+    let filter = page_filter::PageFilter::load(&opts.data_dir)?;
+
+    if let Some(opts::Command::Check) = opts.command {
+        state.write().insert_from_dir(&opts.data_dir, &filter)?;
+        let reports = check::check_links(&*state.read());
+        for report in &reports {
+            println!(
+                "{}: [{}]({}): {}",
+                report.source_path.display(),
+                report.link.text,
+                report.link.url,
+                report.status
+            );
+        }
+        if !reports.is_empty() {
+            bail!("{} problematic links found", reports.len());
+        }
+        return Ok(());
+    }
+
+    if let Some(opts::Command::Retag { ref config }) = opts.command {
+        let grammar =
+            tag::Grammar::new(&settings::Site::load_from(config)?.tags)?;
+        state.write().insert_from_dir(&opts.data_dir, &filter)?;
+        let changes = retag::tag_changes(&*state.read(), &grammar);
+        for change in &changes {
+            let added =
+                change.added.iter().map(|tag| format!("+#{}", tag));
+            let removed =
+                change.removed.iter().map(|tag| format!("-#{}", tag));
+            println!(
+                "{}: {}",
+                change.path.display(),
+                added.chain(removed).collect::<Vec<_>>().join(" ")
+            );
+        }
+        println!("{} pages would change", changes.len());
+        return Ok(());
+    }
+
+    if let Some(opts::Command::Tag(ref cmd)) = opts.command {
+        let (old, new, mode, dry_run) = match cmd {
+            opts::TagCommand::Rename { old, new, dry_run } => {
+                (old, new, rename::Mode::Rename, *dry_run)
+            }
+            opts::TagCommand::Merge {
+                from,
+                into,
+                dry_run,
+            } => (from, into, rename::Mode::Merge, *dry_run),
+        };
+        state.write().insert_from_dir(&opts.data_dir, &filter)?;
+        let changes = rename::plan(&*state.read(), old, new, mode)?;
+        for change in &changes {
+            println!("{}:", change.file_name(&opts.data_dir));
+            print!("{}", change.diff());
+        }
+        if dry_run {
+            println!("{} files would change", changes.len());
+        } else {
+            rename::apply(&state, &changes)?;
+            println!("{} files changed", changes.len());
+        }
+        return Ok(());
+    }
 
     let _watcher = data::FsWatcher::new(
         opts.data_dir.clone(),
         state.clone(),
+        filter.clone(),
     );
 
-    state.write().insert_from_dir(&opts.data_dir).unwrap();
+    state
+        .write()
+        .insert_from_dir(&opts.data_dir, &filter)
+        .unwrap();
 
     web::start(state, settings, opts);
 });
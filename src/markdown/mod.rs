@@ -1,16 +1,80 @@
 use pulldown_cmark::{html, Event, Parser};
+use std::{borrow::Cow, ops::Range};
+
+use crate::tag::{self, Grammar};
 
-use lazy_static::lazy_static;
-use regex::Regex;
 pub type Tag = String;
 pub type RenderedHtml = String;
 pub type Title = String;
 
-pub fn parse_markdown(markdown_text: &str) -> (Vec<Tag>, RenderedHtml, Title) {
-    lazy_static! {
-        static ref RE: Regex = Regex::new(r"#[\w\d]+").unwrap();
+/// Link to another page in the wiki, eg. `[x](/idea/brainwiki)`
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Link {
+    pub text: String,
+    pub url: String,
+    pub tags: Vec<Tag>,
+}
+
+impl Link {
+    /// Returns `None` for external and special (`/~...`) urls
+    fn from_url(url: &str) -> Option<Self> {
+        if !url.starts_with('/') || url.starts_with("//") || url.starts_with("/~") {
+            return None;
+        }
+        let path = url.split(|c| c == '#' || c == '?').next().unwrap_or("");
+        let tags: Vec<Tag> = path
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(tag::normalize)
+            .collect();
+        if tags.is_empty() {
+            return None;
+        }
+
+        Some(Link {
+            text: String::new(),
+            url: url.into(),
+            tags: tags,
+        })
     }
 
+    /// The url without its query and fragment
+    pub fn path(&self) -> &str {
+        self.url.split(|c| c == '#' || c == '?').next().unwrap_or("")
+    }
+}
+
+/// Tracks whether events are inside inline code or code blocks,
+/// where `#tags` don't count
+#[derive(Default)]
+struct CodeLevel(i32);
+
+impl CodeLevel {
+    /// Account for the next `event`
+    fn update(&mut self, event: &Event) {
+        match event {
+            Event::Start(::pulldown_cmark::Tag::Code)
+            | Event::Start(::pulldown_cmark::Tag::CodeBlock(_)) => {
+                self.0 += 1;
+            }
+            Event::End(::pulldown_cmark::Tag::Code)
+            | Event::End(::pulldown_cmark::Tag::CodeBlock(_)) => {
+                assert!(self.0 > 0);
+                self.0 -= 1;
+            }
+            _ => {}
+        }
+    }
+
+    fn in_code(&self) -> bool {
+        self.0 > 0
+    }
+}
+
+pub fn parse_markdown(
+    markdown_text: &str,
+    grammar: &Grammar,
+) -> (Vec<Tag>, RenderedHtml, Title, Vec<Link>) {
     let mut tags = vec![];
     let mut html_buf = String::new();
     let mut in_title = 0u32;
@@ -18,52 +82,59 @@ pub fn parse_markdown(markdown_text: &str) -> (Vec<Tag>, RenderedHtml, Title) {
     let mut backup_title = String::new();
     let max_backup_title_len = 100;
 
-    let mut code_tag_level = 0;
+    let mut links = vec![];
+    let mut cur_link: Option<Link> = None;
+
+    let mut code_level = CodeLevel::default();
     {
         let parser = Parser::new(markdown_text);
 
-        let parser = parser.map(|event| match event {
-            Event::Text(text) => {
-                if code_tag_level == 0 {
-                    for tag in RE.find_iter(&text) {
-                        tags.push(tag.as_str()[1..].to_lowercase());
+        let parser = parser.map(|event| {
+            code_level.update(&event);
+            match event {
+                Event::Text(text) => {
+                    if !code_level.in_code() {
+                        tags.extend(grammar.find_tags(&text));
                     }
-                }
 
-                if backup_title.len() < max_backup_title_len {
-                    let mut append = text.to_string();
-                    append.truncate(max_backup_title_len - backup_title.len());
-                    backup_title.push_str(&append.as_str());
-                }
+                    if backup_title.len() < max_backup_title_len {
+                        let mut append = text.to_string();
+                        append.truncate(max_backup_title_len - backup_title.len());
+                        backup_title.push_str(&append.as_str());
+                    }
 
-                if in_title > 0 {
-                    title += &text.clone().to_string();
-                }
+                    if in_title > 0 {
+                        title += &text.clone().to_string();
+                    }
 
-                Event::Text(text)
-            }
-            Event::Start(::pulldown_cmark::Tag::Code)
-            | Event::Start(::pulldown_cmark::Tag::CodeBlock(_)) => {
-                code_tag_level += 1;
-                event
-            }
-            Event::End(::pulldown_cmark::Tag::Code)
-            | Event::End(::pulldown_cmark::Tag::CodeBlock(_)) => {
-                assert!(code_tag_level >= 0);
-                code_tag_level -= 1;
-                event
-            }
-            Event::Start(::pulldown_cmark::Tag::Header(1)) => {
-                if title.is_empty() {
-                    in_title += 1;
+                    if let Some(ref mut link) = cur_link {
+                        link.text.push_str(&text);
+                    }
+
+                    Event::Text(text)
                 }
-                event
-            }
-            Event::End(::pulldown_cmark::Tag::Header(1)) => {
-                in_title -= 1;
-                event
+                Event::Start(::pulldown_cmark::Tag::Link(ref url, _)) => {
+                    cur_link = Link::from_url(url);
+                    event
+                }
+                Event::End(::pulldown_cmark::Tag::Link(_, _)) => {
+                    if let Some(link) = cur_link.take() {
+                        links.push(link);
+                    }
+                    event
+                }
+                Event::Start(::pulldown_cmark::Tag::Header(1)) => {
+                    if title.is_empty() {
+                        in_title += 1;
+                    }
+                    event
+                }
+                Event::End(::pulldown_cmark::Tag::Header(1)) => {
+                    in_title -= 1;
+                    event
+                }
+                _ => event,
             }
-            _ => event,
         });
 
         html::push_html(&mut html_buf, parser);
@@ -77,12 +148,102 @@ pub fn parse_markdown(markdown_text: &str) -> (Vec<Tag>, RenderedHtml, Title) {
     } else {
         title.trim().to_owned()
     };
-    (tags, html_buf, title)
+    (tags, html_buf, title, links)
+}
+
+/// A `#tag` or an internal link, located in markdown source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Mention {
+    /// The normalized tag of a `#tag`
+    Tag(Tag),
+    /// An internal link, parsed from its url
+    Link(Link),
+}
+
+/// Byte offset of `text` within `source`, if it's a part of it
+fn source_offset(source: &str, text: &Cow<str>) -> Option<usize> {
+    match text {
+        Cow::Borrowed(text) => {
+            let start = source.as_ptr() as usize;
+            let offset = (text.as_ptr() as usize).checked_sub(start)?;
+            if offset + text.len() <= source.len() {
+                Some(offset)
+            } else {
+                None
+            }
+        }
+        // unescaped by the parser, not a part of the source
+        Cow::Owned(_) => None,
+    }
+}
+
+/// Tags and internal links of `markdown_text` with their byte ranges,
+/// sorted; found like `parse_markdown` finds them, so tags in code
+/// are skipped
+///
+/// Each range is returned once, even when reference links share a
+/// definition and so the url.
+pub fn find_mentions(
+    markdown_text: &str,
+    grammar: &Grammar,
+) -> Vec<(Range<usize>, Mention)> {
+    let mut mentions = vec![];
+    let mut code_level = CodeLevel::default();
+
+    for event in Parser::new(markdown_text) {
+        code_level.update(&event);
+        match event {
+            Event::Text(ref text) if !code_level.in_code() => {
+                if let Some(offset) = source_offset(markdown_text, text) {
+                    for (range, tag) in grammar.find_tag_ranges(text) {
+                        mentions.push((
+                            offset + range.start..offset + range.end,
+                            Mention::Tag(tag),
+                        ));
+                    }
+                }
+            }
+            Event::Start(::pulldown_cmark::Tag::Link(ref url, _)) => {
+                let link = Link::from_url(url);
+                let offset = source_offset(markdown_text, url);
+                if let (Some(link), Some(offset)) = (link, offset) {
+                    mentions.push((
+                        offset..offset + url.len(),
+                        Mention::Link(link),
+                    ));
+                }
+            }
+            _ => {}
+        }
+    }
+    mentions.sort_by_key(|(range, _)| range.start);
+    mentions.dedup_by(|(n, _), (m, _)| n == m);
+    mentions
+}
+
+/// Text content of the markdown, without any formatting
+pub fn plain_text(markdown_text: &str) -> String {
+    use pulldown_cmark::Tag;
+
+    let mut text = String::new();
+    for event in Parser::new(markdown_text) {
+        match event {
+            Event::Text(t) => text.push_str(&t),
+            Event::SoftBreak | Event::HardBreak => text.push(' '),
+            Event::End(Tag::Paragraph)
+            | Event::End(Tag::Header(_))
+            | Event::End(Tag::Item)
+            | Event::End(Tag::CodeBlock(_))
+            | Event::End(Tag::TableCell) => text.push('\n'),
+            _ => {}
+        }
+    }
+    text
 }
 
 #[test]
 fn simple() {
-    let (tags, _rendered, _title) = parse_markdown(
+    let (tags, _rendered, _title, _links) = parse_markdown(
         r#"
 Foo bar #X.
 #foo
@@ -92,6 +253,7 @@ Foo bar #X.
 * #list
 
     "#,
+        &Grammar::default(),
     );
 
     assert_eq!(tags, ["bar", "baz", "ciężarkiewicz", "foo", "list", "x"]);
@@ -99,7 +261,7 @@ Foo bar #X.
 
 #[test]
 fn skip_code() {
-    let (tags, _rendered, _title) = parse_markdown(
+    let (tags, _rendered, _title, _links) = parse_markdown(
         r#"
 Foo bar #X.
 
@@ -108,7 +270,71 @@ Foo bar #X.
 
 #CięŻarkiewicz #FOO
     "#,
+        &Grammar::default(),
     );
 
     assert_eq!(tags, ["ciężarkiewicz", "foo", "x"]);
 }
+
+#[test]
+fn plain() {
+    let text = plain_text("# Title\n\nSome *emph* [link](/a/b).\n\n* item");
+
+    assert_eq!(text, "Title\nSome emph link.\nitem\n");
+}
+
+#[test]
+fn internal_links() {
+    let (_tags, _rendered, _title, links) = parse_markdown(
+        r#"
+[My *idea*](/Idea/brainwiki/) [ext](http://example.com)
+[special](/~new) [anchor](/a#section) [root](/)
+    "#,
+        &Grammar::default(),
+    );
+
+    assert_eq!(
+        links,
+        [
+            Link {
+                text: "My idea".into(),
+                url: "/Idea/brainwiki/".into(),
+                tags: vec!["idea".into(), "brainwiki".into()],
+            },
+            Link {
+                text: "anchor".into(),
+                url: "/a#section".into(),
+                tags: vec!["a".into()],
+            },
+        ]
+    );
+}
+
+#[test]
+fn non_ascii_tags() {
+    let (tags, _rendered, _title, links) = parse_markdown(
+        "#Cieżarkiewicz #ŁÓDŹ [x](/Cie%C5%BCarkiewicz/%C5%81%C3%B3d%C5%BA)",
+        &Grammar::default(),
+    );
+
+    assert_eq!(tags, vec!["cieżarkiewicz", "łódź"]);
+    assert_eq!(links[0].tags, tags);
+}
+
+#[test]
+fn configured_grammar() {
+    let grammar = Grammar::new(&crate::settings::TagSettings {
+        extra_chars: "-".into(),
+        min_len: 2,
+        ignore_numeric: true,
+        stop_list: vec!["fff".into()],
+        hierarchy_separator: None,
+    })
+    .unwrap();
+    let (tags, _rendered, _title, _links) = parse_markdown(
+        "Fixes #123, color #fff. #open-source #X `#code-tag`",
+        &grammar,
+    );
+
+    assert_eq!(tags, ["open-source"]);
+}
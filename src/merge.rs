@@ -0,0 +1,294 @@
+//! Line-based three-way merge of concurrent page edits
+//!
+//! Both sides are diffed against the common base; chunks changed on
+//! only one side are taken from that side, chunks changed on both
+//! sides differently are conflicts, marked like `diff3` does.
+
+const CONFLICT_START: &str = "<<<<<<< current\n";
+const CONFLICT_SEPARATOR: &str = "=======\n";
+const CONFLICT_END: &str = ">>>>>>> edited\n";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Merged {
+    /// Merged text, with conflict markers if there were conflicts
+    pub text: String,
+    pub conflicts: usize,
+}
+
+impl Merged {
+    pub fn is_clean(&self) -> bool {
+        self.conflicts == 0
+    }
+}
+
+/// Split `text` into lines, keeping the line endings
+fn split_lines(text: &str) -> Vec<&str> {
+    let mut lines = vec![];
+    let mut start = 0;
+    for (i, ch) in text.char_indices() {
+        if ch == '\n' {
+            lines.push(&text[start..i + 1]);
+            start = i + 1;
+        }
+    }
+    if start < text.len() {
+        lines.push(&text[start..]);
+    }
+    lines
+}
+
+/// For every line of `base`, the index of the line of `other` it is
+/// matched with in their longest common subsequence
+fn matching(base: &[&str], other: &[&str]) -> Vec<Option<usize>> {
+    let mut res = vec![None; base.len()];
+
+    // common prefix and suffix are cheap, and typically most of a page
+    let mut prefix = 0;
+    while prefix < base.len()
+        && prefix < other.len()
+        && base[prefix] == other[prefix]
+    {
+        res[prefix] = Some(prefix);
+        prefix += 1;
+    }
+    let mut suffix = 0;
+    while suffix < base.len() - prefix
+        && suffix < other.len() - prefix
+        && base[base.len() - 1 - suffix]
+            == other[other.len() - 1 - suffix]
+    {
+        res[base.len() - 1 - suffix] = Some(other.len() - 1 - suffix);
+        suffix += 1;
+    }
+
+    let x = &base[prefix..base.len() - suffix];
+    let y = &other[prefix..other.len() - suffix];
+
+    // lcs[i][j] - length of LCS of x[i..] and y[j..]
+    let mut lcs = vec![vec![0u32; y.len() + 1]; x.len() + 1];
+    for i in (0..x.len()).rev() {
+        for j in (0..y.len()).rev() {
+            lcs[i][j] = if x[i] == y[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < x.len() && j < y.len() {
+        if x[i] == y[j] {
+            res[prefix + i] = Some(prefix + j);
+            i += 1;
+            j += 1;
+        } else if lcs[i + 1][j] >= lcs[i][j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    res
+}
+
+fn push_lines(text: &mut String, lines: &[&str]) {
+    for line in lines {
+        text.push_str(line);
+    }
+}
+
+/// Push a side of a conflict, making sure the marker after it starts
+/// on its own line
+fn push_conflict_side(text: &mut String, lines: &[&str]) {
+    push_lines(text, lines);
+    if !lines.is_empty() && !text.ends_with('\n') {
+        text.push('\n');
+    }
+}
+
+/// Merge `current` and `edited`, both derived from `base`
+pub fn merge(base: &str, current: &str, edited: &str) -> Merged {
+    let base = split_lines(base);
+    let current = split_lines(current);
+    let edited = split_lines(edited);
+
+    let match_current = matching(&base, &current);
+    let match_edited = matching(&base, &edited);
+
+    let mut text = String::new();
+    let mut conflicts = 0;
+
+    let (mut i, mut ic, mut ie) = (0, 0, 0);
+    loop {
+        // lines unchanged on both sides
+        while i < base.len()
+            && match_current[i] == Some(ic)
+            && match_edited[i] == Some(ie)
+        {
+            text.push_str(base[i]);
+            i += 1;
+            ic += 1;
+            ie += 1;
+        }
+
+        // the next base line kept on both sides ends the changed chunk
+        let anchor = (i..base.len()).find(|&j| {
+            match_current[j].is_some() && match_edited[j].is_some()
+        });
+        let (j, jc, je) = match anchor {
+            Some(j) => (
+                j,
+                match_current[j].unwrap(),
+                match_edited[j].unwrap(),
+            ),
+            None => (base.len(), current.len(), edited.len()),
+        };
+
+        let chunk_base = &base[i..j];
+        let chunk_current = &current[ic..jc];
+        let chunk_edited = &edited[ie..je];
+
+        if chunk_current == chunk_base {
+            push_lines(&mut text, chunk_edited);
+        } else if chunk_edited == chunk_base
+            || chunk_current == chunk_edited
+        {
+            push_lines(&mut text, chunk_current);
+        } else {
+            conflicts += 1;
+            text.push_str(CONFLICT_START);
+            push_conflict_side(&mut text, chunk_current);
+            text.push_str(CONFLICT_SEPARATOR);
+            push_conflict_side(&mut text, chunk_edited);
+            text.push_str(CONFLICT_END);
+        }
+
+        if anchor.is_none() {
+            break;
+        }
+        i = j;
+        ic = jc;
+        ie = je;
+    }
+
+    Merged {
+        text: text,
+        conflicts: conflicts,
+    }
+}
+
+/// Changed lines between `old` and `new`, prefixed with `-` when
+/// only in `old` and `+` when only in `new`
+pub fn diff(old: &str, new: &str) -> String {
+    let old = split_lines(old);
+    let new = split_lines(new);
+    let match_new = matching(&old, &new);
+
+    let mut text = String::new();
+    let mut push = |prefix: &str, line: &str| {
+        text.push_str(prefix);
+        text.push_str(line);
+        if !line.ends_with('\n') {
+            text.push('\n');
+        }
+    };
+    let mut j = 0;
+    for (i, line) in old.iter().enumerate() {
+        match match_new[i] {
+            Some(k) => {
+                for line in &new[j..k] {
+                    push("+ ", line);
+                }
+                j = k + 1;
+            }
+            None => push("- ", line),
+        }
+    }
+    for line in &new[j..] {
+        push("+ ", line);
+    }
+    text
+}
+
+#[test]
+fn unchanged() {
+    let text = "a\nb\nc\n";
+    let merged = merge(text, text, text);
+    assert!(merged.is_clean());
+    assert_eq!(merged.text, text);
+}
+
+#[test]
+fn changes_on_one_side() {
+    let base = "a\nb\nc\n";
+    let changed = "a\nB\nc\nd\n";
+
+    assert_eq!(merge(base, changed, base).text, changed);
+    assert_eq!(merge(base, base, changed).text, changed);
+}
+
+#[test]
+fn changes_on_both_sides() {
+    let base = "# Title\n\none\ntwo\nthree\n\n#tag\n";
+    let current = "# Title\n\nONE\ntwo\nthree\n\n#tag\n";
+    let edited = "# Title\n\none\ntwo\nthree\nfour\n\n#tag #other\n";
+
+    let merged = merge(base, current, edited);
+    assert!(merged.is_clean());
+    assert_eq!(
+        merged.text,
+        "# Title\n\nONE\ntwo\nthree\nfour\n\n#tag #other\n"
+    );
+}
+
+#[test]
+fn same_change_on_both_sides() {
+    let base = "a\nb\nc\n";
+    let changed = "a\nx\nc\n";
+
+    let merged = merge(base, changed, changed);
+    assert!(merged.is_clean());
+    assert_eq!(merged.text, changed);
+}
+
+#[test]
+fn deletions() {
+    let base = "a\nb\nc\nd\n";
+
+    let merged = merge(base, "b\nc\nd\n", "a\nb\nc\n");
+    assert!(merged.is_clean());
+    assert_eq!(merged.text, "b\nc\n");
+}
+
+#[test]
+fn conflict() {
+    let base = "a\nb\nc";
+    let merged = merge(base, "a\nx\nc", "a\ny\nc");
+
+    assert_eq!(merged.conflicts, 1);
+    assert_eq!(
+        merged.text,
+        "a\n<<<<<<< current\nx\n=======\ny\n>>>>>>> edited\nc"
+    );
+}
+
+#[test]
+fn conflict_without_trailing_newline() {
+    let merged = merge("a\nb", "a\nx", "a\ny");
+
+    assert_eq!(merged.conflicts, 1);
+    assert_eq!(
+        merged.text,
+        "a\n<<<<<<< current\nx\n=======\ny\n>>>>>>> edited\n"
+    );
+}
+
+#[test]
+fn line_diff() {
+    assert_eq!(diff("a\nb\n", "a\nb\n"), "");
+    assert_eq!(
+        diff("#old #idea\ntext\n", "#new #idea\ntext\nmore"),
+        "- #old #idea\n+ #new #idea\n+ more\n"
+    );
+}
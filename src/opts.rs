@@ -1,11 +1,69 @@
 use std::path::PathBuf;
 use structopt::StructOpt;
 
+use crate::settings::TokenScope;
+
 #[derive(Debug, StructOpt, Clone)]
 pub enum Command {
     #[structopt(name = "passwd")]
     /// Set password
     Password,
+    #[structopt(name = "check")]
+    /// Report broken, ambiguous and broadened links
+    Check,
+    #[structopt(name = "token")]
+    /// Manage API tokens
+    Token(TokenCommand),
+    #[structopt(name = "retag")]
+    /// Show how page tags would change under the `[tags]` settings
+    /// of another config file
+    Retag {
+        #[structopt(parse(from_os_str))]
+        config: PathBuf,
+    },
+    #[structopt(name = "tag")]
+    /// Rename and merge tags in all pages
+    Tag(TagCommand),
+}
+
+#[derive(Debug, StructOpt, Clone)]
+pub enum TokenCommand {
+    #[structopt(name = "create")]
+    /// Create a token and print it
+    Create {
+        name: String,
+        /// read or read-write
+        #[structopt(long = "scope", default_value = "read")]
+        scope: TokenScope,
+    },
+    #[structopt(name = "list")]
+    /// List tokens
+    List,
+    #[structopt(name = "revoke")]
+    /// Delete a token
+    Revoke { name: String },
+}
+
+#[derive(Debug, StructOpt, Clone)]
+pub enum TagCommand {
+    #[structopt(name = "rename")]
+    /// Rename a tag to one no page has yet
+    Rename {
+        old: String,
+        new: String,
+        /// Only print what would change
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+    },
+    #[structopt(name = "merge")]
+    /// Replace a tag with another one that's already used
+    Merge {
+        from: String,
+        into: String,
+        /// Only print what would change
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+    },
 }
 
 #[derive(Debug, StructOpt, Clone)]
@@ -27,6 +85,9 @@ pub struct Opts {
     pub command: Option<Command>,
     /// Run locally - no password needed for editing
     pub local: bool,
+    /// Commit every change to the git repository of the data dir
+    #[structopt(long = "git")]
+    pub git: bool,
     //    #[structopt(flatten)]
     //    verbosity: Verbosity,
 }
@@ -1,8 +1,19 @@
-use crate::markdown;
+use crate::markdown::{self, Link};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone};
 use std::fs;
 use std::path::Path;
 
-use crate::Result;
+use crate::{
+    tag::{self, Grammar},
+    Result,
+};
+
+/// Hash of page markdown, see `Page::md_hash`
+pub fn md_hash(md: &str) -> String {
+    let digest =
+        ring::digest::digest(&ring::digest::SHA256, md.as_bytes());
+    hex::encode(digest.as_ref())
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Page {
@@ -10,12 +21,60 @@ pub struct Page {
     pub html: String,
     pub md: String,
     pub tags: Vec<String>,
+    /// Implied by `tags` through `tags.toml`, filled in when indexed
+    #[serde(default)]
+    pub derived_tags: Vec<String>,
+    /// Outgoing links to other pages
+    pub links: Vec<Link>,
+    /// From the `Creation-Date:` line, or the file metadata
+    pub created: Option<DateTime<Local>>,
+    /// Last modification of the file
+    pub modified: Option<DateTime<Local>>,
+}
+
+const CREATION_DATE_PREFIX: &str = "Creation-Date:";
+
+/// Parse the `Creation-Date:` line, as inserted by `tpl::new`
+///
+/// The time zone name is ignored, the date is taken as local.
+fn parse_creation_date(md: &str) -> Option<DateTime<Local>> {
+    let line = md
+        .lines()
+        .map(str::trim)
+        .find(|line| line.starts_with(CREATION_DATE_PREFIX))?;
+    let mut words = line[CREATION_DATE_PREFIX.len()..].split_whitespace();
+    let date = words.next()?;
+    let naive = match words.next() {
+        Some(time) => ["%F %H-%M", "%F %H:%M", "%F %H:%M:%S"]
+            .iter()
+            .filter_map(|format| {
+                NaiveDateTime::parse_from_str(
+                    &format!("{} {}", date, time),
+                    format,
+                )
+                .ok()
+            })
+            .next(),
+        None => None,
+    };
+    let naive = match naive {
+        Some(naive) => naive,
+        None => NaiveDate::parse_from_str(date, "%F")
+            .ok()?
+            .and_hms(0, 0, 0),
+    };
+    Local.from_local_datetime(&naive).earliest()
 }
 
 impl Page {
-    pub fn from_markdown(markdown: String) -> Self {
-        let (tags, html, title) = markdown::parse_markdown(&markdown);
+    pub fn from_markdown(
+        markdown: String,
+        grammar: &Grammar,
+    ) -> Self {
+        let (tags, html, title, links) =
+            markdown::parse_markdown(&markdown, grammar);
 
+        let created = parse_creation_date(&markdown);
         let page = Page {
             html: html,
             md: markdown,
@@ -25,22 +84,50 @@ impl Page {
                 title
             },
             tags: tags,
+            derived_tags: vec![],
+            links: links,
+            created: created,
+            modified: None,
         };
 
         page
     }
-    pub fn read_from_file(path: &Path) -> Result<Self> {
+    pub fn read_from_file(
+        path: &Path,
+        grammar: &Grammar,
+    ) -> Result<Self> {
         let md = fs::read_to_string(path)?;
+        let metadata = fs::metadata(path)?;
 
-        Ok(Self::from_markdown(md))
+        let mut page = Self::from_markdown(md, grammar);
+        page.modified = metadata.modified().ok().map(DateTime::from);
+        if page.created.is_none() {
+            page.created = metadata
+                .created()
+                .ok()
+                .map(DateTime::from)
+                .or(page.modified);
+        }
+        Ok(page)
+    }
+
+    /// Hash of `md`, identifying the version of the page an editor
+    /// started from
+    pub fn md_hash(&self) -> String {
+        md_hash(&self.md)
     }
 
     pub fn url(&self) -> String {
-        "/".to_string() + self.tags.join("/").as_str()
+        self.to_full_url(true)
     }
 
     pub fn to_full_url(&self, prefer_exact: bool) -> String {
-        let mut location = String::from("/") + self.tags.join("/").as_str();
+        let segments: Vec<String> = self
+            .tags
+            .iter()
+            .map(|t| tag::to_url_segment(t))
+            .collect();
+        let mut location = String::from("/") + segments.join("/").as_str();
         if !prefer_exact {
             location += "/";
         }
@@ -66,3 +153,23 @@ impl Page {
         filename
     }
 }
+
+#[test]
+fn creation_date() {
+    let date = |md: &str| {
+        Page::from_markdown(md.into(), &Grammar::default())
+            .created
+            .map(|created| created.format("%F %H:%M").to_string())
+    };
+
+    assert_eq!(
+        date("Creation-Date: 2018-07-10 14-30 CEST\n\n# Title"),
+        Some("2018-07-10 14:30".into())
+    );
+    assert_eq!(
+        date("Creation-Date: 2018-07-10"),
+        Some("2018-07-10 00:00".into())
+    );
+    assert_eq!(date("# Title"), None);
+    assert_eq!(date("Creation-Date: yesterday"), None);
+}
@@ -0,0 +1,122 @@
+//! Deciding which files in the data dir are pages
+//!
+//! Used both when loading the data dir and by the fs watcher, so the two
+//! always agree.
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::{Path, PathBuf};
+
+use crate::{
+    data::TRASH_DIR_NAME, history::HISTORY_DIR_NAME, Result,
+};
+
+/// Gitignore-syntax file in the root of the data dir
+pub const IGNORE_FILE_NAME: &str = ".brainwikiignore";
+
+/// Directories that are never scanned for pages
+const SKIPPED_DIRS: &[&str] =
+    &[".git", TRASH_DIR_NAME, HISTORY_DIR_NAME];
+
+/// Canonicalize the parent of `path`
+///
+/// Works for paths that don't exist anymore (eg. removed files),
+/// as long as their parent directory does.
+pub fn normalize_path(path: &Path) -> PathBuf {
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) => parent
+            .canonicalize()
+            .map(|parent| parent.join(name))
+            .unwrap_or_else(|_| path.into()),
+        _ => path.into(),
+    }
+}
+
+fn is_temp_file_name(name: &str) -> bool {
+    name.starts_with(".#")
+        || name.starts_with('#')
+        || name.ends_with('~')
+        || name.ends_with(".tmp")
+        || name.ends_with(".swp")
+        || name.ends_with(".swx")
+}
+
+#[derive(Clone, Debug)]
+pub struct PageFilter {
+    root: PathBuf,
+    gitignore: Gitignore,
+}
+
+impl PageFilter {
+    pub fn load(root: &Path) -> Result<Self> {
+        let root = root.canonicalize()?;
+        let mut builder = GitignoreBuilder::new(&root);
+        let ignore_path = root.join(IGNORE_FILE_NAME);
+        if ignore_path.exists() {
+            if let Some(e) = builder.add(&ignore_path) {
+                Err(e)?;
+            }
+        }
+
+        Ok(PageFilter {
+            gitignore: builder.build()?,
+            root: root,
+        })
+    }
+
+    /// Should `path` (file or directory) be skipped
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let path = normalize_path(path);
+        let rel_path = match path.strip_prefix(&self.root) {
+            Ok(rel_path) => rel_path,
+            Err(_) => return true,
+        };
+
+        if rel_path.components().any(|c| {
+            SKIPPED_DIRS.iter().any(|dir| c.as_os_str() == *dir)
+        }) {
+            return true;
+        }
+
+        if rel_path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(is_temp_file_name)
+            .unwrap_or(false)
+        {
+            return true;
+        }
+
+        self.gitignore
+            .matched_path_or_any_parents(rel_path, is_dir)
+            .is_ignore()
+    }
+
+    pub fn is_page(&self, path: &Path) -> bool {
+        path.extension().and_then(|e| e.to_str()) == Some("md")
+            && !self.is_ignored(path, false)
+    }
+}
+
+#[test]
+fn ignore_rules() {
+    use std::fs;
+
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let root = dir.path();
+    fs::create_dir_all(root.join("journal/2018")).unwrap();
+    fs::create_dir_all(root.join("drafts")).unwrap();
+    fs::write(root.join(IGNORE_FILE_NAME), "drafts/\n*.private.md\n")
+        .unwrap();
+
+    let filter = PageFilter::load(root).unwrap();
+
+    assert!(filter.is_page(&root.join("a.md")));
+    assert!(filter.is_page(&root.join("journal/2018/a.md")));
+    assert!(!filter.is_page(&root.join("a.txt")));
+    assert!(!filter.is_page(&root.join("drafts/a.md")));
+    assert!(!filter.is_page(&root.join("journal/a.private.md")));
+    assert!(!filter.is_page(&root.join(".git/a.md")));
+    assert!(!filter.is_page(&root.join(".trash/a.md")));
+    assert!(!filter.is_page(&root.join(".#a.md")));
+    assert!(filter.is_ignored(&root.join("drafts"), true));
+    assert!(!filter.is_ignored(&root.join("journal"), true));
+}
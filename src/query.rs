@@ -0,0 +1,149 @@
+//! Boolean tag queries
+//!
+//! ```text
+//! /project/-done          - #project and not #done
+//! /~q/(rust|go)+idea      - #idea and either #rust or #go
+//! ```
+//!
+//! `+`, `/` and whitespace mean AND, `|` means OR, a leading `-` negates
+//! and parentheses group. AND binds stronger than OR.
+use std::{iter::Peekable, str::Chars};
+
+use crate::{tag, Result};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    Tag(String),
+    Not(Box<Query>),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+}
+
+fn is_and_separator(ch: char) -> bool {
+    ch == '+' || ch == '/' || ch.is_whitespace()
+}
+
+fn is_tag_char(ch: char) -> bool {
+    !is_and_separator(ch) && !"()|".contains(ch)
+}
+
+/// Does `text` use any syntax beyond plain `/a/b/c` tags
+pub fn is_query(text: &str) -> bool {
+    text.contains(|ch| "()|+".contains(ch))
+        || text.split('/').any(|segment| segment.starts_with('-'))
+}
+
+impl Query {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut parser = Parser {
+            chars: text.chars().peekable(),
+        };
+        let query = parser.parse_or()?;
+        parser.skip_separators();
+        if let Some(ch) = parser.chars.next() {
+            bail!("Unexpected `{}` in query", ch);
+        }
+        Ok(query)
+    }
+
+    /// Tags that pages must have to match (not negated)
+    pub fn positive_tags(&self) -> Vec<String> {
+        let mut tags = vec![];
+        self.collect_positive_tags(&mut tags);
+        tags
+    }
+
+    fn collect_positive_tags(&self, tags: &mut Vec<String>) {
+        match self {
+            Query::Tag(tag) => {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+            Query::Not(_) => {}
+            Query::And(queries) | Query::Or(queries) => {
+                for query in queries {
+                    query.collect_positive_tags(tags);
+                }
+            }
+        }
+    }
+}
+
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+}
+
+impl<'a> Parser<'a> {
+    fn skip_separators(&mut self) {
+        while self.chars.peek().cloned().map(is_and_separator)
+            == Some(true)
+        {
+            self.chars.next();
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Query> {
+        let mut queries = vec![self.parse_and()?];
+        while self.chars.peek() == Some(&'|') {
+            self.chars.next();
+            queries.push(self.parse_and()?);
+        }
+
+        Ok(if queries.len() == 1 {
+            queries.pop().unwrap()
+        } else {
+            Query::Or(queries)
+        })
+    }
+
+    fn parse_and(&mut self) -> Result<Query> {
+        let mut queries = vec![self.parse_unary()?];
+        loop {
+            self.skip_separators();
+            match self.chars.peek() {
+                None | Some('|') | Some(')') => break,
+                _ => queries.push(self.parse_unary()?),
+            }
+        }
+
+        Ok(if queries.len() == 1 {
+            queries.pop().unwrap()
+        } else {
+            Query::And(queries)
+        })
+    }
+
+    fn parse_unary(&mut self) -> Result<Query> {
+        self.skip_separators();
+        match self.chars.peek().cloned() {
+            Some('-') => {
+                self.chars.next();
+                Ok(Query::Not(Box::new(self.parse_unary()?)))
+            }
+            Some('(') => {
+                self.chars.next();
+                let query = self.parse_or()?;
+                self.skip_separators();
+                if self.chars.next() != Some(')') {
+                    bail!("Missing `)` in query");
+                }
+                Ok(query)
+            }
+            _ => {
+                let mut tag = String::new();
+                while let Some(ch) = self.chars.peek().cloned() {
+                    if !is_tag_char(ch) {
+                        break;
+                    }
+                    tag.push(ch);
+                    self.chars.next();
+                }
+                if tag.is_empty() {
+                    bail!("Expected a tag in query");
+                }
+                Ok(Query::Tag(tag::normalize(&tag)))
+            }
+        }
+    }
+}
@@ -0,0 +1,348 @@
+//! Renaming and merging tags in all pages
+//!
+//! `#old` is rewritten in the markdown wherever `parse_markdown`
+//! would find it, so not in code, and so are internal links using
+//! `old`. With hierarchical tags, descendants like `old/child` are
+//! renamed too.
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    data::{State, SyncState},
+    markdown::{self, Mention},
+    merge,
+    page::{self, Page},
+    tag::{self, Grammar},
+    Result,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// `new` must not exist yet
+    Rename,
+    /// `new` must exist already
+    Merge,
+}
+
+/// New content of a page file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileChange {
+    pub path: PathBuf,
+    pub old_md: String,
+    pub new_md: String,
+}
+
+impl FileChange {
+    pub fn diff(&self) -> String {
+        merge::diff(&self.old_md, &self.new_md)
+    }
+
+    /// Path relative to `data_dir`, for display
+    pub fn file_name(&self, data_dir: &Path) -> String {
+        self.path
+            .strip_prefix(data_dir)
+            .unwrap_or(&self.path)
+            .display()
+            .to_string()
+    }
+}
+
+struct Rename<'a> {
+    old: &'a str,
+    new: &'a str,
+    separator: Option<char>,
+}
+
+impl<'a> Rename<'a> {
+    fn tag(&self, tag: &str) -> Option<String> {
+        if tag == self.old {
+            return Some(self.new.into());
+        }
+        let separator = self.separator?;
+        if tag.starts_with(self.old)
+            && tag[self.old.len()..].starts_with(separator)
+        {
+            Some(format!("{}{}", self.new, &tag[self.old.len()..]))
+        } else {
+            None
+        }
+    }
+
+    fn url(&self, url: &str) -> Option<String> {
+        let path_len =
+            url.find(|c| c == '#' || c == '?').unwrap_or(url.len());
+        let (path, rest) = url.split_at(path_len);
+
+        let mut changed = false;
+        let segments: Vec<String> = path
+            .split('/')
+            .map(|segment| match self.tag(&tag::normalize(segment)) {
+                Some(ref tag) if !segment.is_empty() => {
+                    changed = true;
+                    tag::to_url_segment(tag)
+                }
+                _ => segment.to_owned(),
+            })
+            .collect();
+        if changed {
+            Some(segments.join("/") + rest)
+        } else {
+            None
+        }
+    }
+
+    fn md(&self, md: &str, grammar: &Grammar) -> String {
+        let mut new_md = String::new();
+        let mut copied = 0;
+        for (range, mention) in markdown::find_mentions(md, grammar) {
+            let replacement = match mention {
+                Mention::Tag(ref tag) => {
+                    self.tag(tag).map(|tag| format!("#{}", tag))
+                }
+                Mention::Link(_) => self.url(&md[range.clone()]),
+            };
+            if let Some(replacement) = replacement {
+                new_md.push_str(&md[copied..range.start]);
+                new_md.push_str(&replacement);
+                copied = range.end;
+            }
+        }
+        new_md.push_str(&md[copied..]);
+        new_md
+    }
+}
+
+/// `md` with mentions of tag `old` replaced by `new`
+pub fn rewrite_md(
+    md: &str,
+    grammar: &Grammar,
+    old: &str,
+    new: &str,
+) -> String {
+    Rename {
+        old: old,
+        new: new,
+        separator: grammar.separator(),
+    }
+    .md(md, grammar)
+}
+
+/// Files that would change by renaming `old` to `new`, sorted by
+/// path
+pub fn plan(
+    state: &State,
+    old: &str,
+    new: &str,
+    mode: Mode,
+) -> Result<Vec<FileChange>> {
+    let grammar = state.tag_grammar();
+    let old = tag::normalize(old);
+    let new = tag::normalize(new);
+    if grammar.find_tags(&format!("#{}", new)) != vec![new.clone()] {
+        bail!("Invalid tag: {}", new);
+    }
+    if old == new {
+        bail!("Tags are the same");
+    }
+    let new_exists =
+        !state.pages_with_tags(&[new.clone()]).is_empty();
+    match mode {
+        Mode::Rename if new_exists => {
+            bail!("#{} already exists, merge into it instead", new)
+        }
+        Mode::Merge if !new_exists => {
+            bail!("#{} doesn't exist, rename to it instead", new)
+        }
+        _ => {}
+    }
+
+    let mut changes: Vec<FileChange> = state
+        .pages_by_id
+        .iter()
+        .filter_map(|(page_id, page)| {
+            let new_md = rewrite_md(&page.md, grammar, &old, &new);
+            if new_md == page.md {
+                return None;
+            }
+            Some(FileChange {
+                path: state.path_by_id[page_id].clone(),
+                old_md: page.md.clone(),
+                new_md: new_md,
+            })
+        })
+        .collect();
+    changes.sort_by(|n, m| n.path.cmp(&m.path));
+    Ok(changes)
+}
+
+/// Identifies a plan, to check that the previewed plan is the one
+/// being applied
+pub fn plan_hash(changes: &[FileChange]) -> String {
+    let mut text = String::new();
+    for change in changes {
+        text.push_str(&format!(
+            "{}\0{}\0{}\0",
+            change.path.display(),
+            page::md_hash(&change.old_md),
+            page::md_hash(&change.new_md)
+        ));
+    }
+    page::md_hash(&text)
+}
+
+/// Write all `changes`, each file atomically
+///
+/// Nothing is written if any file differs from what was planned. If
+/// writing fails midway, the error names the files already written.
+pub fn apply(
+    data: &SyncState,
+    changes: &[FileChange],
+) -> Result<()> {
+    let _edit_lock = data.lock_edits();
+    let changed: Vec<&FileChange> = changes
+        .iter()
+        .filter(|change| {
+            fs::read_to_string(&change.path).ok().as_ref()
+                != Some(&change.old_md)
+        })
+        .collect();
+    if !changed.is_empty() {
+        bail!(
+            "Changed since planned, nothing written: {}",
+            changed
+                .iter()
+                .map(|change| change.path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+
+    let grammar = data.read().tag_grammar().clone();
+    for (i, change) in changes.iter().enumerate() {
+        let page =
+            Page::from_markdown(change.new_md.clone(), &grammar);
+        if let Err(e) = data.replace_file(&change.path, &page) {
+            let written: Vec<_> = changes[..i]
+                .iter()
+                .map(|change| change.path.display().to_string())
+                .collect();
+            bail!(
+                "Failed to write {}: {}; already written: {}",
+                change.path.display(),
+                e,
+                if written.is_empty() {
+                    "none".into()
+                } else {
+                    written.join(", ")
+                }
+            );
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn rewrite() {
+    let md = "# Old #Old\n\n\
+              Text #old, #older `#old` [x](/old/idea/#top)\n\n    \
+              #old in code\n";
+    assert_eq!(
+        rewrite_md(md, &Grammar::default(), "old", "new"),
+        "# Old #new\n\n\
+         Text #new, #older `#old` [x](/new/idea/#top)\n\n    \
+         #old in code\n"
+    );
+}
+
+#[test]
+fn rewrite_shared_reference() {
+    assert_eq!(
+        rewrite_md(
+            "[a][r] and [b][r]\n\n[r]: /old/idea\n",
+            &Grammar::default(),
+            "old",
+            "new"
+        ),
+        "[a][r] and [b][r]\n\n[r]: /new/idea\n"
+    );
+}
+
+#[test]
+fn rewrite_hierarchical() {
+    use crate::settings::TagSettings;
+
+    let grammar = Grammar::new(&TagSettings {
+        hierarchy_separator: Some('/'),
+        ..TagSettings::default()
+    })
+    .unwrap();
+    assert_eq!(
+        rewrite_md(
+            "#lang/rust #language [r](/lang%2Frust)",
+            &grammar,
+            "lang",
+            "code"
+        ),
+        "#code/rust #language [r](/code%2Frust)"
+    );
+}
+
+#[test]
+fn plan_and_apply() {
+    use crate::page_filter::PageFilter;
+
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(root.join("a.md"), "# A\n#js #idea").unwrap();
+    fs::write(root.join("b.md"), "# B\n#javascript [a](/js/idea)")
+        .unwrap();
+    fs::write(root.join("c.md"), "# C\n#rust").unwrap();
+
+    let data = SyncState::new();
+    data.write()
+        .insert_from_dir(&root, &PageFilter::load(&root).unwrap())
+        .unwrap();
+
+    let state = data.read();
+    assert!(plan(&state, "js", "javascript", Mode::Rename).is_err());
+    assert!(plan(&state, "js", "ecmascript", Mode::Merge).is_err());
+    assert!(plan(&state, "js", "no way", Mode::Rename).is_err());
+    assert!(plan(&state, "js", "JS", Mode::Rename).is_err());
+
+    let changes =
+        plan(&state, "JS", "javascript", Mode::Merge).unwrap();
+    drop(state);
+    assert_eq!(changes.len(), 2);
+    assert_eq!(changes[0].file_name(&root), "a.md");
+    assert_eq!(
+        changes[0].diff(),
+        "- #js #idea\n+ #javascript #idea\n"
+    );
+    assert_eq!(
+        changes[1].new_md,
+        "# B\n#javascript [a](/javascript/idea)"
+    );
+
+    let hash = plan_hash(&changes);
+    assert_eq!(hash, plan_hash(&changes));
+    assert_ne!(hash, plan_hash(&changes[1..]));
+
+    fs::write(root.join("b.md"), "# B\n#javascript edited").unwrap();
+    assert!(apply(&data, &changes).is_err());
+    assert_eq!(
+        fs::read_to_string(root.join("a.md")).unwrap(),
+        "# A\n#js #idea"
+    );
+    fs::write(root.join("b.md"), &changes[1].old_md).unwrap();
+
+    apply(&data, &changes).unwrap();
+    assert_eq!(
+        fs::read_to_string(root.join("a.md")).unwrap(),
+        "# A\n#javascript #idea"
+    );
+    let state = data.read();
+    assert_eq!(state.pages_with_tags(&["javascript".into()]).len(), 2);
+    assert!(state.pages_with_tags(&["js".into()]).is_empty());
+}
@@ -0,0 +1,76 @@
+//! Previewing how a different tag grammar would re-index pages
+use std::{collections::BTreeSet, path::PathBuf};
+
+use crate::{data::State, page::Page, tag::Grammar};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TagChange {
+    pub path: PathBuf,
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+/// Pages of `state` whose tags would differ if parsed with
+/// `grammar`, sorted by path
+pub fn tag_changes(
+    state: &State,
+    grammar: &Grammar,
+) -> Vec<TagChange> {
+    let mut changes: Vec<TagChange> = state
+        .pages_by_id
+        .iter()
+        .filter_map(|(page_id, page)| {
+            let old: BTreeSet<String> =
+                page.tags.iter().cloned().collect();
+            let new_page =
+                Page::from_markdown(page.md.clone(), grammar);
+            let new: BTreeSet<String> = state
+                .canonical_tags(&new_page.tags)
+                .into_iter()
+                .collect();
+            if old == new {
+                return None;
+            }
+            Some(TagChange {
+                path: state.path_by_id[page_id].clone(),
+                added: new.difference(&old).cloned().collect(),
+                removed: old.difference(&new).cloned().collect(),
+            })
+        })
+        .collect();
+    changes.sort_by(|n, m| n.path.cmp(&m.path));
+    changes
+}
+
+#[test]
+fn changes() {
+    use crate::{page_filter::PageFilter, settings::TagSettings};
+    use std::fs;
+
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let root = dir.path().canonicalize().unwrap();
+    fs::write(root.join("a.md"), "# A\n#bug #123 #open-source")
+        .unwrap();
+    fs::write(root.join("b.md"), "# B\n#idea").unwrap();
+
+    let mut state = State::new();
+    state
+        .insert_from_dir(&root, &PageFilter::load(&root).unwrap())
+        .unwrap();
+
+    let grammar = Grammar::new(&TagSettings {
+        extra_chars: "-".into(),
+        ignore_numeric: true,
+        ..TagSettings::default()
+    })
+    .unwrap();
+    assert_eq!(
+        tag_changes(&state, &grammar),
+        vec![TagChange {
+            path: root.join("a.md"),
+            added: vec!["open-source".into()],
+            removed: vec!["123".into(), "open".into()],
+        }]
+    );
+    assert!(tag_changes(&state, &Grammar::default()).is_empty());
+}
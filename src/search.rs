@@ -0,0 +1,270 @@
+//! Full-text search over page titles and bodies
+//!
+//! A simple in-memory inverted index, ranked with BM25.
+use std::collections::{HashMap, HashSet};
+
+use crate::{data::PageId, markdown, page::Page, tag};
+
+const BM25_K1: f64 = 1.2;
+const BM25_B: f64 = 0.75;
+
+/// Title terms are counted as if they appeared this many times
+const TITLE_BOOST: u32 = 3;
+
+const SNIPPET_LEN: usize = 200;
+
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// Free-text terms and `#tag` filters of a search query
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct TextQuery {
+    pub terms: Vec<String>,
+    pub tags: Vec<String>,
+}
+
+impl TextQuery {
+    pub fn parse(q: &str) -> Self {
+        let mut query = TextQuery::default();
+        for word in q.split_whitespace() {
+            if word.starts_with('#') {
+                let tag = tag::normalize(&word[1..]);
+                if !tag.is_empty() {
+                    query.tags.push(tag);
+                }
+            } else {
+                query.terms.extend(tokenize(word));
+            }
+        }
+        query
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.terms.is_empty() && self.tags.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Hit {
+    pub page_id: PageId,
+    pub score: f64,
+}
+
+#[derive(Default, Debug)]
+pub struct TextIndex {
+    /// term -> page -> term frequency
+    postings: HashMap<String, HashMap<PageId, u32>>,
+    doc_lengths: HashMap<PageId, u32>,
+    total_length: u64,
+}
+
+fn page_terms(page: &Page) -> HashMap<String, u32> {
+    let mut terms = HashMap::new();
+    for term in tokenize(&page.title) {
+        *terms.entry(term).or_insert(0) += TITLE_BOOST;
+    }
+    for term in tokenize(&markdown::plain_text(&page.md)) {
+        *terms.entry(term).or_insert(0) += 1;
+    }
+    terms
+}
+
+impl TextIndex {
+    pub fn insert(&mut self, page_id: PageId, page: &Page) {
+        let terms = page_terms(page);
+        let length: u32 = terms.values().sum();
+
+        for (term, freq) in terms {
+            self.postings
+                .entry(term)
+                .or_insert(Default::default())
+                .insert(page_id, freq);
+        }
+        self.doc_lengths.insert(page_id, length);
+        self.total_length += u64::from(length);
+    }
+
+    pub fn remove(&mut self, page_id: PageId, page: &Page) {
+        for term in page_terms(page).keys() {
+            let now_empty =
+                if let Some(docs) = self.postings.get_mut(term) {
+                    docs.remove(&page_id);
+                    docs.is_empty()
+                } else {
+                    false
+                };
+            if now_empty {
+                self.postings.remove(term);
+            }
+        }
+        if let Some(length) = self.doc_lengths.remove(&page_id) {
+            self.total_length -= u64::from(length);
+        }
+    }
+
+    /// Rank pages matching any of `terms`
+    ///
+    /// If `candidates` is given, only these pages are considered. With
+    /// no `terms` all candidates are returned with a zero score.
+    pub fn search(
+        &self,
+        terms: &[String],
+        candidates: Option<&HashSet<PageId>>,
+    ) -> Vec<Hit> {
+        let allowed = |id: &PageId| {
+            candidates.map(|c| c.contains(id)).unwrap_or(true)
+        };
+
+        let mut scores: HashMap<PageId, f64> = HashMap::new();
+        if terms.is_empty() {
+            if let Some(candidates) = candidates {
+                for id in candidates {
+                    scores.insert(*id, 0.0);
+                }
+            }
+        }
+
+        let num_docs = self.doc_lengths.len() as f64;
+        let avg_length = if self.doc_lengths.is_empty() {
+            1.0
+        } else {
+            self.total_length as f64 / num_docs
+        };
+
+        let unique_terms: HashSet<&String> = terms.iter().collect();
+        for term in unique_terms {
+            let docs = match self.postings.get(term) {
+                Some(docs) => docs,
+                None => continue,
+            };
+            let doc_freq = docs.len() as f64;
+            let idf = (1.0
+                + (num_docs - doc_freq + 0.5) / (doc_freq + 0.5))
+                .ln();
+
+            for (id, freq) in docs.iter().filter(|(id, _)| allowed(*id))
+            {
+                let freq = f64::from(*freq);
+                let length = f64::from(self.doc_lengths[id]);
+                let norm = BM25_K1
+                    * (1.0 - BM25_B + BM25_B * length / avg_length);
+                *scores.entry(*id).or_insert(0.0) +=
+                    idf * freq * (BM25_K1 + 1.0) / (freq + norm);
+            }
+        }
+
+        let mut hits: Vec<Hit> = scores
+            .into_iter()
+            .map(|(page_id, score)| Hit { page_id, score })
+            .collect();
+        hits.sort_by(|n, m| m.score.partial_cmp(&n.score).unwrap());
+        hits
+    }
+}
+
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+/// Html snippet of `text` around the first matching term, with
+/// all matching words wrapped in `<mark>`
+pub fn snippet(text: &str, terms: &[String]) -> String {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let is_match = |word: &str| {
+        tokenize(word).iter().any(|token| terms.contains(token))
+    };
+
+    let first = words.iter().position(|w| is_match(w)).unwrap_or(0);
+    let start = first.saturating_sub(8);
+
+    let mut snippet = String::new();
+    if start > 0 {
+        snippet.push_str("… ");
+    }
+    let mut len = 0;
+    for word in &words[start..] {
+        if len > SNIPPET_LEN {
+            snippet.push_str(" …");
+            break;
+        }
+        if len > 0 {
+            snippet.push(' ');
+        }
+        if is_match(word) {
+            snippet.push_str("<mark>");
+            snippet.push_str(&escape_html(word));
+            snippet.push_str("</mark>");
+        } else {
+            snippet.push_str(&escape_html(word));
+        }
+        len += word.len() + 1;
+    }
+    snippet
+}
+
+#[cfg(test)]
+fn page(md: &str) -> Page {
+    Page::from_markdown(md.into(), &Default::default())
+}
+
+#[test]
+fn query_parse() {
+    assert_eq!(
+        TextQuery::parse("Borrow #Rust checker,fun"),
+        TextQuery {
+            terms: vec![
+                "borrow".into(),
+                "checker".into(),
+                "fun".into()
+            ],
+            tags: vec!["rust".into()],
+        }
+    );
+}
+
+#[test]
+fn ranking() {
+    let mut index = TextIndex::default();
+    index.insert(0.into(), &page("# Cats\n\ncats cats and dogs"));
+    index.insert(1.into(), &page("# Dogs\n\ndogs only, one cat"));
+    index.insert(2.into(), &page("# Birds\n\nnothing here"));
+
+    let hits = index.search(&["cats".into()], None);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].page_id, 0.into());
+
+    let hits = index.search(&["dogs".into()], None);
+    assert_eq!(hits.len(), 2);
+    assert_eq!(hits[0].page_id, 1.into());
+
+    let candidates = [0.into()].iter().cloned().collect();
+    let hits = index.search(&["dogs".into()], Some(&candidates));
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].page_id, 0.into());
+
+    index.remove(1.into(), &page("# Dogs\n\ndogs only, one cat"));
+    let hits = index.search(&["dogs".into()], None);
+    assert_eq!(hits.len(), 1);
+    assert_eq!(hits[0].page_id, 0.into());
+}
+
+#[test]
+fn snippet_highlights() {
+    let s = snippet("a <b> Rust, c", &["rust".into()]);
+    assert_eq!(s, "a &lt;b&gt; <mark>Rust,</mark> c");
+}
@@ -1,5 +1,9 @@
 use crate::Result;
-use std::path::{Path, PathBuf};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use crate::util::{deserialize_as_hex, serialize_as_hex};
 
@@ -20,6 +24,121 @@ pub struct Site {
         deserialize_with = "deserialize_as_hex"
     )]
     pub web_salt: Vec<u8>,
+    #[serde(default)]
+    pub api_tokens: Vec<ApiToken>,
+    #[serde(default)]
+    pub cookie: CookieSettings,
+    #[serde(default)]
+    pub tags: TagSettings,
+    /// Header carrying the client address, like `X-Forwarded-For`,
+    /// when behind a reverse proxy; otherwise every client has the
+    /// proxy's address in the audit log and login throttling
+    #[serde(default)]
+    pub client_ip_header: Option<String>,
+}
+
+/// What counts as a `#tag`, the `[tags]` table in `config.toml`
+///
+/// Changes apply to pages as they are loaded, so restart after
+/// editing; `brainwiki retag` shows what would change.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+pub struct TagSettings {
+    /// Allowed inside tags besides letters, digits and `_`, like
+    /// `-` for `#open-source`
+    #[serde(default)]
+    pub extra_chars: String,
+    /// Shorter tags are ignored
+    #[serde(default)]
+    pub min_len: usize,
+    /// Ignore tags made only of digits, like issue numbers
+    #[serde(default)]
+    pub ignore_numeric: bool,
+    /// Never tags, like `fff` from css colors
+    #[serde(default)]
+    pub stop_list: Vec<String>,
+    /// Splits tags into a hierarchy, like `/` for `#lang/rust`
+    #[serde(default)]
+    pub hierarchy_separator: Option<char>,
+}
+
+/// `SameSite` attribute of the session cookie
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+/// Session cookie attributes, the `[cookie]` table in `config.toml`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct CookieSettings {
+    /// Send only over https; enable when serving over https
+    #[serde(default)]
+    pub secure: bool,
+    #[serde(default = "default_same_site")]
+    pub same_site: SameSite,
+    /// Keep the login for that long, instead of until the browser
+    /// is closed
+    #[serde(default)]
+    pub max_age_secs: Option<i64>,
+}
+
+fn default_same_site() -> SameSite {
+    SameSite::Lax
+}
+
+impl Default for CookieSettings {
+    fn default() -> Self {
+        CookieSettings {
+            secure: false,
+            same_site: default_same_site(),
+            max_age_secs: None,
+        }
+    }
+}
+
+/// What an API token allows
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenScope {
+    Read,
+    ReadWrite,
+}
+
+impl FromStr for TokenScope {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "read" => Ok(TokenScope::Read),
+            "read-write" => Ok(TokenScope::ReadWrite),
+            _ => bail!("Unknown scope: {} (use read or read-write)", s),
+        }
+    }
+}
+
+impl fmt::Display for TokenScope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            TokenScope::Read => "read",
+            TokenScope::ReadWrite => "read-write",
+        })
+    }
+}
+
+/// Named token for scripts, sent as `Authorization: Bearer <token>`
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct ApiToken {
+    pub name: String,
+    pub scope: TokenScope,
+    /// Tokens are random, so a plain hash is enough to protect them
+    pub hashed_token: String,
+}
+
+fn hash_token(token: &str) -> String {
+    let digest =
+        ring::digest::digest(&ring::digest::SHA256, token.as_bytes());
+    hex::encode(digest.as_ref())
 }
 
 fn rand_salt() -> Vec<u8> {
@@ -38,6 +157,10 @@ impl Default for Site {
             hashed_password: None,
             version: 0,
             web_salt: rand_salt(),
+            api_tokens: vec![],
+            cookie: CookieSettings::default(),
+            tags: TagSettings::default(),
+            client_ip_header: None,
         }
     }
 }
@@ -68,9 +191,69 @@ impl Site {
             Some(libpasta::hash_password(cleartext));
     }
 
+    /// Add a token, returning it in cleartext - the only time it is
+    /// available
+    pub fn create_token(
+        &mut self,
+        name: String,
+        scope: TokenScope,
+    ) -> Result<String> {
+        if self.api_tokens.iter().any(|token| token.name == name) {
+            bail!("Token {} already exists", name);
+        }
+        let token = hex::encode(&rand_salt());
+        self.api_tokens.push(ApiToken {
+            name: name,
+            scope: scope,
+            hashed_token: hash_token(&token),
+        });
+        Ok(token)
+    }
+
+    pub fn revoke_token(&mut self, name: &str) -> Result<()> {
+        let len = self.api_tokens.len();
+        self.api_tokens.retain(|token| token.name != name);
+        if self.api_tokens.len() == len {
+            bail!("No token named {}", name);
+        }
+        Ok(())
+    }
+
+    /// The stored token matching cleartext `token`
+    pub fn find_token(&self, token: &str) -> Option<&ApiToken> {
+        let hashed = hash_token(token);
+        self.api_tokens.iter().find(|api_token| {
+            ring::constant_time::verify_slices_are_equal(
+                api_token.hashed_token.as_bytes(),
+                hashed.as_bytes(),
+            )
+            .is_ok()
+        })
+    }
+
     pub fn write_to_dir(&self, dir: &Path) -> Result<()> {
         let file_path = Self::dir_to_file(dir);
         file::put(file_path, toml::to_vec(&self)?)?;
         Ok(())
     }
 }
+
+#[test]
+fn api_tokens() {
+    let mut site = Site::default();
+    let token = site
+        .create_token("capture".into(), TokenScope::ReadWrite)
+        .unwrap();
+    assert!(site.create_token("capture".into(), TokenScope::Read).is_err());
+
+    assert_eq!(
+        site.find_token(&token).map(|token| token.scope),
+        Some(TokenScope::ReadWrite)
+    );
+    assert!(site.find_token("guess").is_none());
+    assert!(!site.api_tokens[0].hashed_token.contains(&token));
+
+    site.revoke_token("capture").unwrap();
+    assert!(site.find_token(&token).is_none());
+    assert!(site.revoke_token("capture").is_err());
+}
@@ -0,0 +1,323 @@
+//! Tag normalization
+//!
+//! The same tag can arrive written in markdown, percent-encoded in
+//! an url, or typed in a search box, in any case and Unicode
+//! normalization form. Everything that compares tags first passes
+//! them through `normalize`, and everything that puts tags in urls
+//! uses `to_url_segment`, so `normalize(to_url_segment(tag))` always
+//! gives back the tag.
+//!
+//! What counts as a tag in markdown is decided by a `Grammar`,
+//! configured with the `[tags]` table in `config.toml`. With a
+//! hierarchy separator, like `/`, `#lang/rust` is a child of `lang`
+//! and pages having it match `lang` as well.
+//!
+//! `tags.toml` in the data dir declares `Rules` applied to every
+//! page when it's indexed.
+
+use regex::Regex;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    fs,
+    ops::Range,
+    path::Path,
+};
+use unicode_normalization::UnicodeNormalization;
+use url::percent_encoding::{
+    percent_decode, utf8_percent_encode, PATH_SEGMENT_ENCODE_SET,
+};
+
+use crate::{settings::TagSettings, Result};
+
+/// Percent-decoded, lowercased, NFC form of `tag`
+pub fn normalize(tag: &str) -> String {
+    let decoded = percent_decode(tag.as_bytes()).decode_utf8_lossy();
+    decoded.to_lowercase().nfc().collect()
+}
+
+/// `tag` encoded to be used as one url path segment
+pub fn to_url_segment(tag: &str) -> String {
+    utf8_percent_encode(tag, PATH_SEGMENT_ENCODE_SET).to_string()
+}
+
+/// Escaped for use in a regex character class
+fn class_chars(chars: &str) -> String {
+    chars
+        .chars()
+        .map(|ch| format!("\\x{{{:x}}}", ch as u32))
+        .collect()
+}
+
+/// `tag` and its ancestors, longest first: `lang/rust`, `lang`
+pub fn with_ancestors(tag: &str, separator: Option<char>) -> Vec<&str> {
+    let mut tags = vec![tag];
+    if let Some(separator) = separator {
+        tags.extend(
+            tag.rmatch_indices(separator).map(|(i, _)| &tag[..i]),
+        );
+    }
+    tags
+}
+
+/// Finds `#tags` in text
+#[derive(Debug, Clone)]
+pub struct Grammar {
+    re: Regex,
+    separator: Option<char>,
+    min_len: usize,
+    ignore_numeric: bool,
+    /// Normalized
+    stop_list: HashSet<String>,
+}
+
+impl Grammar {
+    pub fn new(settings: &TagSettings) -> Result<Self> {
+        // extra characters only inside, so `#open-source-` at the
+        // end of a sentence is still `open-source`; `#` right after a
+        // word character, like in `example.com/page#section`, starts
+        // no tag
+        let part = format!(
+            r"\w(?:[\w{}]*\w)?",
+            class_chars(&settings.extra_chars)
+        );
+        let re = match settings.hierarchy_separator {
+            Some(separator) => Regex::new(&format!(
+                r"\B#{part}(?:[{separator}]{part})*",
+                part = part,
+                separator = class_chars(&separator.to_string())
+            ))?,
+            None => Regex::new(&format!(r"\B#{}", part))?,
+        };
+
+        Ok(Grammar {
+            re: re,
+            separator: settings.hierarchy_separator,
+            min_len: settings.min_len,
+            ignore_numeric: settings.ignore_numeric,
+            stop_list: settings
+                .stop_list
+                .iter()
+                .map(|tag| normalize(tag))
+                .collect(),
+        })
+    }
+
+    pub fn separator(&self) -> Option<char> {
+        self.separator
+    }
+
+    fn accepts(&self, tag: &str) -> bool {
+        tag.chars().count() >= self.min_len
+            && !(self.ignore_numeric
+                && tag.chars().all(|ch| ch.is_numeric()))
+            && !self.stop_list.contains(tag)
+    }
+
+    /// Normalized tags written in `text`, in order, with the byte
+    /// ranges of their `#tag` text
+    pub fn find_tag_ranges(
+        &self,
+        text: &str,
+    ) -> Vec<(Range<usize>, String)> {
+        self.re
+            .find_iter(text)
+            .map(|m| (m.start()..m.end(), normalize(&m.as_str()[1..])))
+            .filter(|(_, tag)| self.accepts(tag))
+            .collect()
+    }
+
+    /// Normalized tags written in `text`, in order
+    pub fn find_tags(&self, text: &str) -> Vec<String> {
+        self.find_tag_ranges(text)
+            .into_iter()
+            .map(|(_, tag)| tag)
+            .collect()
+    }
+}
+
+impl Default for Grammar {
+    /// Any letters, digits and `_`
+    fn default() -> Self {
+        Grammar::new(&TagSettings::default()).unwrap()
+    }
+}
+
+pub const RULES_FILE_NAME: &str = "tags.toml";
+
+/// Aliases and implications between tags
+///
+/// ```toml
+/// [aliases]
+/// js = "javascript"
+///
+/// [implies]
+/// rust = ["programming"]
+/// ```
+///
+/// Aliases are replaced by their canonical tag everywhere; aliases
+/// of aliases are not followed. Implied tags are added to pages
+/// having the implying tag, transitively.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Rules {
+    /// Alias -> canonical tag
+    #[serde(default)]
+    aliases: HashMap<String, String>,
+    /// Tag -> tags it implies
+    #[serde(default)]
+    implies: HashMap<String, Vec<String>>,
+}
+
+impl Rules {
+    /// Rules from `tags.toml` in `data_dir`, if there is one
+    pub fn load(data_dir: &Path) -> Result<Self> {
+        let path = data_dir.join(RULES_FILE_NAME);
+        if !path.exists() {
+            return Ok(Default::default());
+        }
+        Rules::parse(&fs::read_to_string(&path)?)
+    }
+
+    pub fn parse(text: &str) -> Result<Self> {
+        let rules: Rules = toml::from_str(text)?;
+        let aliases: HashMap<String, String> = rules
+            .aliases
+            .iter()
+            .map(|(alias, tag)| (normalize(alias), normalize(tag)))
+            .collect();
+        let canonical = |tag: &str| {
+            let tag = normalize(tag);
+            aliases.get(&tag).cloned().unwrap_or(tag)
+        };
+        let mut implies = HashMap::new();
+        for (tag, implied) in &rules.implies {
+            implies
+                .entry(canonical(tag))
+                .or_insert_with(Vec::new)
+                .extend(implied.iter().map(|tag| canonical(tag)));
+        }
+
+        Ok(Rules {
+            aliases: aliases,
+            implies: implies,
+        })
+    }
+
+    /// The tag `tag` is an alias of, or `tag` itself
+    pub fn canonical<'a>(&'a self, tag: &'a str) -> &'a str {
+        self.aliases.get(tag).map(String::as_str).unwrap_or(tag)
+    }
+
+    /// Tags implied by canonical `tags`, except `tags` themselves
+    pub fn implied(&self, tags: &[String]) -> Vec<String> {
+        let mut implied = BTreeSet::new();
+        let mut to_check: Vec<&String> = tags.iter().collect();
+        while let Some(tag) = to_check.pop() {
+            let direct =
+                self.implies.get(tag).map(Vec::as_slice).unwrap_or(&[]);
+            for tag in direct {
+                if !tags.contains(tag) && implied.insert(tag.clone()) {
+                    to_check.push(tag);
+                }
+            }
+        }
+        implied.into_iter().collect()
+    }
+}
+
+#[test]
+fn non_ascii() {
+    let tag = normalize("Cieżarkiewicz");
+    assert_eq!(tag, "cieżarkiewicz");
+    assert_eq!(normalize("Cie%C5%BCarkiewicz"), tag);
+    assert_eq!(normalize("CIE%C5%BBARKIEWICZ"), tag);
+    // `z` followed by a combining dot above
+    assert_eq!(normalize("Ciez\u{307}arkiewicz"), tag);
+
+    assert_eq!(to_url_segment(&tag), "cie%C5%BCarkiewicz");
+    assert_eq!(normalize(&to_url_segment(&tag)), tag);
+}
+
+#[test]
+fn round_trip() {
+    for tag in &["rust", "łódź", "café", "日本語", "50%", "a/b"] {
+        let tag = normalize(tag);
+        assert_eq!(normalize(&to_url_segment(&tag)), tag);
+        assert!(!to_url_segment(&tag).contains('/'));
+    }
+}
+
+#[test]
+fn grammar() {
+    let default = Grammar::default();
+    assert_eq!(
+        default.find_tags("#Rust #123 #fff #open-source x#y"),
+        vec!["rust", "123", "fff", "open"]
+    );
+    assert_eq!(
+        default.find_tags("see example.com/page#section, (#ok)"),
+        vec!["ok"]
+    );
+
+    let grammar = Grammar::new(&TagSettings {
+        extra_chars: "-".into(),
+        min_len: 2,
+        ignore_numeric: true,
+        stop_list: vec!["FFF".into()],
+        hierarchy_separator: None,
+    })
+    .unwrap();
+    assert_eq!(
+        grammar.find_tags("#Rust #123 #fff #open-source- #a #b2"),
+        vec!["rust", "open-source", "b2"]
+    );
+}
+
+#[test]
+fn hierarchy() {
+    let grammar = Grammar::new(&TagSettings {
+        hierarchy_separator: Some('/'),
+        ..TagSettings::default()
+    })
+    .unwrap();
+    assert_eq!(
+        grammar.find_tags("#Lang/Rust #lang/ #a//b #x/y/z."),
+        vec!["lang/rust", "lang", "a", "x/y/z"]
+    );
+    assert_eq!(
+        with_ancestors("x/y/z", grammar.separator()),
+        vec!["x/y/z", "x/y", "x"]
+    );
+    assert_eq!(with_ancestors("x/y", None), vec!["x/y"]);
+}
+
+#[test]
+fn rules() {
+    let rules = Rules::parse(
+        r#"
+[aliases]
+JS = "JavaScript"
+golang = "go"
+
+[implies]
+js = ["programming"]
+go = ["programming", "golang"]
+programming = ["computers"]
+computers = ["programming"]
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(rules.canonical("js"), "javascript");
+    assert_eq!(rules.canonical("javascript"), "javascript");
+    assert_eq!(
+        rules.implied(&["javascript".into()]),
+        vec!["computers", "programming"]
+    );
+    // implied aliases are canonical too, cycles end
+    assert_eq!(
+        rules.implied(&["go".into(), "programming".into()]),
+        vec!["computers"]
+    );
+    assert!(rules.implied(&["rust".into()]).is_empty());
+    assert!(Rules::parse("aliases = 1").is_err());
+}
@@ -0,0 +1,237 @@
+//! Slowing down password guessing
+//!
+//! Failed logins are counted per client address and globally. Past
+//! a few free attempts every failure doubles the wait before the
+//! next attempt, and too many failures from one address lock it out
+//! for a while. The global count only adds waits, so guessing from
+//! many addresses can't lock the owner out.
+//!
+//! Behind a reverse proxy all clients share the proxy's address
+//! unless `client_ip_header` is set in `config.toml`.
+
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// All durations in seconds
+struct Policy {
+    /// Failures allowed before any waiting
+    free_failures: u32,
+    base_delay: u64,
+    max_delay: u64,
+    /// Failures after which the login is locked for `lockout`, if
+    /// ever
+    lockout_failures: Option<u32>,
+    lockout: u64,
+    /// Failures are forgotten after that long without another one
+    forget_after: u64,
+}
+
+const PER_ADDR: Policy = Policy {
+    free_failures: 3,
+    base_delay: 1,
+    max_delay: 60,
+    lockout_failures: Some(10),
+    lockout: 15 * 60,
+    forget_after: 60 * 60,
+};
+
+/// Catches guessing spread over many addresses
+const GLOBAL: Policy = Policy {
+    free_failures: 20,
+    base_delay: 1,
+    max_delay: 60,
+    lockout_failures: None,
+    lockout: 0,
+    forget_after: 60 * 60,
+};
+
+#[derive(Debug, Clone, Copy)]
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+impl Failures {
+    fn blocked_until(&self, policy: &Policy) -> Option<Instant> {
+        if policy
+            .lockout_failures
+            .map(|lockout_failures| self.count >= lockout_failures)
+            .unwrap_or(false)
+        {
+            return Some(self.last + Duration::from_secs(policy.lockout));
+        }
+        if self.count < policy.free_failures {
+            return None;
+        }
+        let exp = (self.count - policy.free_failures).min(16);
+        let delay = (policy.base_delay << exp).min(policy.max_delay);
+        Some(self.last + Duration::from_secs(delay))
+    }
+
+    fn is_forgotten(&self, policy: &Policy, now: Instant) -> bool {
+        let forget_after = policy.forget_after.max(policy.lockout);
+        now >= self.last + Duration::from_secs(forget_after)
+    }
+}
+
+/// Wait still needed before `failures` allow another attempt
+fn wait(
+    failures: Option<&Failures>,
+    policy: &Policy,
+    now: Instant,
+) -> Option<Duration> {
+    let until = failures?.blocked_until(policy)?;
+    if until > now {
+        Some(until - now)
+    } else {
+        None
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    by_addr: HashMap<String, Failures>,
+    global: Option<Failures>,
+}
+
+impl Inner {
+    fn forget_old(&mut self, now: Instant) {
+        self.by_addr
+            .retain(|_, failures| !failures.is_forgotten(&PER_ADDR, now));
+        if self
+            .global
+            .map(|failures| failures.is_forgotten(&GLOBAL, now))
+            .unwrap_or(false)
+        {
+            self.global = None;
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct LoginThrottle {
+    inner: Mutex<Inner>,
+}
+
+impl LoginThrottle {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// How long `addr` has to wait before trying again, if at all
+    pub fn check(&self, addr: &str, now: Instant) -> Option<Duration> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.forget_old(now);
+
+        let by_addr = wait(inner.by_addr.get(addr), &PER_ADDR, now);
+        let global = wait(inner.global.as_ref(), &GLOBAL, now);
+        by_addr.into_iter().chain(global).max()
+    }
+
+    pub fn record_failure(&self, addr: &str, now: Instant) {
+        let mut inner = self.inner.lock().unwrap();
+        inner.forget_old(now);
+
+        let record = |failures: Option<Failures>| Failures {
+            count: failures.map(|f| f.count).unwrap_or(0) + 1,
+            last: now,
+        };
+        let by_addr = record(inner.by_addr.get(addr).cloned());
+        inner.by_addr.insert(addr.to_owned(), by_addr);
+        inner.global = Some(record(inner.global));
+    }
+
+    /// The password is known, so failures from `addr` were typos
+    ///
+    /// The global count stays, it may include someone else's guesses.
+    pub fn record_success(&self, addr: &str) {
+        self.inner.lock().unwrap().by_addr.remove(addr);
+    }
+}
+
+#[test]
+fn backoff_and_lockout() {
+    let throttle = LoginThrottle::new();
+    let start = Instant::now();
+    let secs = |n| start + Duration::from_secs(n);
+
+    for _ in 0..3 {
+        assert_eq!(throttle.check("a", start), None);
+        throttle.record_failure("a", start);
+    }
+    // waits double after free failures
+    assert_eq!(throttle.check("a", start), Some(Duration::from_secs(1)));
+    assert_eq!(throttle.check("a", secs(1)), None);
+    throttle.record_failure("a", secs(1));
+    assert_eq!(
+        throttle.check("a", secs(1)),
+        Some(Duration::from_secs(2))
+    );
+
+    // other addresses are unaffected
+    assert_eq!(throttle.check("b", secs(1)), None);
+
+    let mut now = secs(1);
+    for _ in 4..10 {
+        now += Duration::from_secs(60);
+        throttle.record_failure("a", now);
+    }
+    assert_eq!(
+        throttle.check("a", now),
+        Some(Duration::from_secs(15 * 60))
+    );
+
+    // forgotten after a while
+    now += Duration::from_secs(60 * 60);
+    assert_eq!(throttle.check("a", now), None);
+}
+
+#[test]
+fn success_resets() {
+    let throttle = LoginThrottle::new();
+    let now = Instant::now();
+
+    for _ in 0..5 {
+        throttle.record_failure("a", now);
+    }
+    assert!(throttle.check("a", now).is_some());
+    throttle.record_success("a");
+    assert_eq!(throttle.check("a", now), None);
+}
+
+#[test]
+fn global_limit() {
+    let throttle = LoginThrottle::new();
+    let now = Instant::now();
+
+    for i in 0..20 {
+        throttle.record_failure(&i.to_string(), now);
+    }
+    assert_eq!(
+        throttle.check("fresh", now),
+        Some(Duration::from_secs(1))
+    );
+}
+
+#[test]
+fn global_limit_never_locks_out() {
+    let throttle = LoginThrottle::new();
+    let now = Instant::now();
+
+    for i in 0..1000 {
+        throttle.record_failure(&i.to_string(), now);
+    }
+    assert_eq!(
+        throttle.check("fresh", now),
+        Some(Duration::from_secs(60))
+    );
+
+    throttle.record_success("fresh");
+    assert_eq!(
+        throttle.check("fresh", now),
+        Some(Duration::from_secs(60))
+    );
+}
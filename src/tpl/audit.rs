@@ -0,0 +1,46 @@
+use stpl::{
+    html::{code, h2, p, table, tbody, td, th, thead, tr},
+    Render,
+};
+
+use super::{base, misc::*};
+use crate::{audit::Entry, search::escape_html};
+
+#[derive(Clone)]
+pub struct Data<'a> {
+    pub base: base::Data<'a>,
+    /// Newest first
+    pub entries: Vec<Entry>,
+}
+
+fn entry_row(entry: &Entry) -> impl Render {
+    tr((
+        td(escape_html(&entry.time)),
+        td(code(escape_html(&entry.addr))),
+        td(escape_html(&entry.who)),
+        td(escape_html(&entry.action)),
+    ))
+}
+
+pub fn page(data: &Data) -> impl Render {
+    let list: Box<dyn Render> = if data.entries.is_empty() {
+        Box::new(p("Nothing logged yet."))
+    } else {
+        Box::new(table.class("table table-sm")((
+            thead(tr((th("Time"), th("Address"), th("Who"), th("Action")))),
+            tbody(data.entries.iter().map(entry_row).collect::<Vec<_>>()),
+        )))
+    };
+
+    let content = (
+        breadcrumb_from_tags(&["Audit log".into()]),
+        row((col_menu(()), col((h2("Audit Log"), list)))),
+    );
+
+    base::base_with_js(
+        &data.base,
+        Box::new(content),
+        Box::new(()),
+        Box::new(()),
+    )
+}
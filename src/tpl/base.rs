@@ -10,6 +10,16 @@ pub struct Data<'a> {
     pub can_edit: bool,
     pub can_login: bool,
     pub site_settings: &'a Site,
+    /// Must be sent with every form and script changing anything
+    pub csrf_token: String,
+}
+
+/// Hidden form field with the CSRF token
+pub fn csrf_input(data: &Data) -> impl Render {
+    input
+        .attr("type", "hidden")
+        .name("csrf_token")
+        .value(data.csrf_token.clone())
 }
 
 pub fn search_form(_data: &Data) -> impl Render {
@@ -21,16 +31,20 @@ pub fn search_form(_data: &Data) -> impl Render {
         input
             .id("search-query")
             .class("form-control")
-            .placeholder("Tags...")
+            .placeholder("Tags or #tags and text...")
             .attr("type", "text")
             .name("q"),
-        span.class("input-group-btn")(button
-            .id("search-button")
-            .type_("submit")
-            .class("btn btn-outline-secondary")(
-            (
-            "Search",
-        )
+        span.class("input-group-btn")((
+            button
+                .id("search-button")
+                .type_("submit")
+                .class("btn btn-outline-secondary")("Search"),
+            button
+                .id("search-text-button")
+                .type_("submit")
+                .name("mode")
+                .value("text")
+                .class("btn btn-outline-secondary")("Text"),
         )),
     )))
 }
@@ -73,12 +87,13 @@ pub fn navbar(
             data.can_edit.as_some(buttons),
             search_form(data),
             (data.can_edit && data.can_login)
-                .as_some(form.action("/~logout").method("post")(button
-                    .name("logout-button")
-                    .id("logout-button")
-                    .class("btn btn-outline-warning mx-1")
-                    .value("logout")(
-                    "Logout"
+                .as_some(form.action("/~logout").method("post")((
+                    csrf_input(data),
+                    button
+                        .name("logout-button")
+                        .id("logout-button")
+                        .class("btn btn-outline-warning mx-1")
+                        .value("logout")("Logout"),
                 ))),
             (!data.can_edit && data.can_login).as_some(a
                 .id("login-button")
@@ -120,6 +135,7 @@ pub fn base_with_js(
                 meta.name("viewport").content("width=device-width, initial-scale=1, shrink-to-fit=no"),
                 meta.name("description").content(""),
                 meta.name("author").content(data.site_settings.author.clone()),
+                meta.name("csrf-token").content(data.csrf_token.clone()),
                 title(data.title.clone()),
 
                 (
@@ -147,9 +163,11 @@ pub fn base_with_js(
                     .integrity("sha384-alpBpkh1PFOepccYVYDB4do5UnbKysX5WZXm3XxPqe5iKTfUKjNkCk9SaVuEZflJ")
                     .crossorigin("anonymous"),
                 flash_js,
+                script.type_("text/javascript")(raw(CSRF_JS)),
                 js,
                 )
             )))
         ))
     )
 }
+const CSRF_JS: &str = include_str!("csrf.js");
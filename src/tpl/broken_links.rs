@@ -0,0 +1,54 @@
+use stpl::{
+    html::{a, code, h2, p, table, tbody, td, th, thead, tr},
+    Render,
+};
+
+use super::{base, misc::*};
+use crate::{check::LinkReport, search::escape_html};
+
+#[derive(Clone)]
+pub struct Data<'a> {
+    pub base: base::Data<'a>,
+    pub reports: Vec<LinkReport>,
+}
+
+fn report_row(report: &LinkReport) -> impl Render {
+    tr((
+        td(a.href(report.source.url())(report.source.title.clone())),
+        td(escape_html(&report.link.text)),
+        td(code(escape_html(&report.link.url))),
+        td(report.status.to_string()),
+    ))
+}
+
+pub fn page(data: &Data) -> impl Render {
+    let reports: Box<dyn Render> = if data.reports.is_empty() {
+        Box::new(p("All links resolve to exactly one page."))
+    } else {
+        Box::new(table.class("table table-sm")((
+            thead(tr((
+                th("Page"),
+                th("Link text"),
+                th("Target"),
+                th("Problem"),
+            ))),
+            tbody(
+                data.reports.iter().map(report_row).collect::<Vec<_>>(),
+            ),
+        )))
+    };
+
+    let content = (
+        breadcrumb_from_tags(&["Broken links".into()]),
+        row((col_menu(()), col((h2("Broken links"), reports)))),
+    );
+
+    let buttons = ();
+
+    base::base_with_js(
+        &data.base,
+        Box::new(content),
+        Box::new(buttons),
+        Box::new(()),
+    )
+}
@@ -0,0 +1,103 @@
+use stpl::{
+    html::{a, button, code, div, form, h2, h4, input, li, p, ul},
+    Render,
+};
+
+use super::{base, misc::*};
+use crate::page::Page;
+
+use boolinator::Boolinator;
+
+/// A page together with its file, for pages that share tags
+#[derive(Clone, Debug)]
+pub struct PageFile {
+    pub page: Page,
+    /// `/~file/...` url viewing exactly this page
+    pub file_url: String,
+    /// Path relative to the data dir
+    pub file_name: String,
+}
+
+#[derive(Clone)]
+pub struct Data<'a> {
+    pub base: base::Data<'a>,
+    pub conflicts: Vec<Vec<PageFile>>,
+}
+
+/// Form appending a distinguishing tag to a page
+pub fn add_tag_form(
+    base: &base::Data,
+    page_file: &PageFile,
+) -> impl Render {
+    form.class("form-inline d-inline-flex mx-1")
+        .action("/~conflicts/add-tag")
+        .method("post")((
+        base::csrf_input(base),
+        input
+            .attr("type", "hidden")
+            .name("file")
+            .value(page_file.file_url.clone()),
+        input
+            .class("form-control form-control-sm")
+            .attr("type", "text")
+            .name("tag")
+            .placeholder("new-tag"),
+        button
+            .type_("submit")
+            .class("btn btn-sm btn-outline-primary mx-1")(
+            "Add tag"
+        ),
+    ))
+}
+
+pub fn page_file_item(base: &base::Data, page_file: &PageFile) -> impl Render {
+    li((
+        a.href(page_file.file_url.clone())(page_file.page.title.clone()),
+        " ",
+        code(page_file.file_name.clone()),
+        base.can_edit.as_some(add_tag_form(base, page_file)),
+    ))
+}
+
+pub fn page(data: &Data) -> impl Render {
+    let conflicts: Box<dyn Render> = if data.conflicts.is_empty() {
+        Box::new(p("No two pages share the same tags."))
+    } else {
+        Box::new(
+            data.conflicts
+                .iter()
+                .map(|pages| {
+                    div.class("mb-3")((
+                        h4(pages[0]
+                            .page
+                            .tags
+                            .iter()
+                            .map(|tag| format!("#{}", tag))
+                            .collect::<Vec<_>>()
+                            .join(" ")),
+                        ul(pages
+                            .iter()
+                            .map(|page_file| {
+                                page_file_item(&data.base, page_file)
+                            })
+                            .collect::<Vec<_>>()),
+                    ))
+                })
+                .collect::<Vec<_>>(),
+        )
+    };
+
+    let content = (
+        breadcrumb_from_tags(&["Conflicts".into()]),
+        row((col_menu(()), col((h2("Pages with identical tags"), conflicts)))),
+    );
+
+    let buttons = ();
+
+    base::base_with_js(
+        &data.base,
+        Box::new(content),
+        Box::new(buttons),
+        Box::new(()),
+    )
+}
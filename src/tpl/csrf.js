@@ -0,0 +1,6 @@
+// Every script request changing anything has to carry the token
+$.ajaxSetup({
+  headers: {
+    'X-CSRF-Token': $('meta[name="csrf-token"]').attr('content')
+  }
+});
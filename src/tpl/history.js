@@ -0,0 +1,24 @@
+$("#restore").click(function(){
+  const url = $(this).data("page-url");
+  $.ajax({
+    contentType: 'application/json',
+    type: 'PUT',
+    url: url,
+    data: JSON.stringify({
+      text: $("#revision-md").val()
+    }),
+    success: function(data) {
+      window.location = data.redirect || url;
+    },
+    error:function(data) {
+      if (data.status == 409) {
+        alert('Conflict. Another page already has the tags of this revision.');
+      } else if (data.status == 401) {
+        alert('Unauthorized.');
+      } else {
+        alert('Unknown error. Status: ' + data.status);
+      }
+    },
+    dataType: 'json'
+  });
+});
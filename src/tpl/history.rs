@@ -0,0 +1,93 @@
+use stpl::{
+    html::{a, button, div, h2, h4, li, p, raw, script, textarea, ul},
+    Render,
+};
+
+use super::{base, misc::*};
+use crate::{history::Revision, page::Page, search::escape_html};
+
+use boolinator::Boolinator;
+
+/// An old revision being looked at
+#[derive(Clone, Debug)]
+pub struct ShownRevision {
+    pub id: String,
+    pub page: Page,
+}
+
+#[derive(Clone)]
+pub struct Data<'a> {
+    pub base: base::Data<'a>,
+    /// Current version of the page
+    pub page: Page,
+    pub revisions: Vec<Revision>,
+    pub revision: Option<ShownRevision>,
+}
+
+fn revision_url(page: &Page, id: &str) -> String {
+    format!("/~history{}?rev={}", page.url(), id)
+}
+
+fn shown_revision(data: &Data, revision: &ShownRevision) -> impl Render {
+    div.id("revision")((
+        h4(format!("Revision {}", revision.id)),
+        data.base.can_edit.as_some((
+            textarea
+                .id("revision-md")
+                .attr("style", "display: none;")(
+                escape_html(&revision.page.md),
+            ),
+            button
+                .id("restore")
+                .class("btn btn-outline-warning my-2")
+                .attr("data-page-url", data.page.url())(
+                "Restore this revision"
+            ),
+        )),
+        div.class("border rounded p-2")(revision.page.html.clone()),
+    ))
+}
+
+pub fn page(data: &Data) -> impl Render {
+    let revisions: Box<dyn Render> = if data.revisions.is_empty() {
+        Box::new(p("No previous revisions."))
+    } else {
+        Box::new(ul(data
+            .revisions
+            .iter()
+            .map(|revision| {
+                li(a.href(revision_url(&data.page, &revision.id))(
+                    revision.time.format("%F %T").to_string(),
+                ))
+            })
+            .collect::<Vec<_>>()))
+    };
+
+    let content = (
+        breadcrumb_from_tags(&data.page.tags.as_slice()),
+        row((
+            col_menu((h4("Revisions"), revisions)),
+            col((
+                h2((
+                    "History of ",
+                    a.href(data.page.url())(data.page.title.clone()),
+                )),
+                data.revision
+                    .as_ref()
+                    .map(|revision| shown_revision(data, revision)),
+            )),
+        )),
+    );
+
+    let buttons = ();
+
+    let js = script.type_("text/javascript")(raw(HISTORY_JS));
+
+    base::base_with_js(
+        &data.base,
+        Box::new(content),
+        Box::new(buttons),
+        Box::new(js),
+    )
+}
+const HISTORY_JS: &str = include_str!("history.js");
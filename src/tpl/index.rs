@@ -1,3 +1,4 @@
+use boolinator::Boolinator;
 use stpl::{html::*, Render};
 
 use super::{
@@ -14,6 +15,28 @@ pub struct Data<'a> {
     pub pages: Vec<Page>,
     pub narrowing_tags: data::NarrowingTagsSet,
     pub matching_tags: Vec<String>,
+    /// Pages are sorted by modification time, not title
+    pub sort_by_modified: bool,
+}
+
+fn sort_links(data: &Data) -> impl Render {
+    let link = |text: &'static str, url: String, active: bool| {
+        if active {
+            Box::new(strong(text)) as Box<dyn Render>
+        } else {
+            Box::new(a.href(url)(text))
+        }
+    };
+    p.class("small")((
+        "Sort by: ",
+        link("title", data.cur_url.clone(), !data.sort_by_modified),
+        " | ",
+        link(
+            "modification date",
+            format!("{}?sort=modified", data.cur_url),
+            data.sort_by_modified,
+        ),
+    ))
 }
 
 pub fn page(data: &Data) -> impl Render {
@@ -23,15 +46,23 @@ pub fn page(data: &Data) -> impl Render {
             col_menu(misc::narrowing_tags_col(
                 &data.cur_url,
                 &data.narrowing_tags,
+                data.base.site_settings.tags.hierarchy_separator,
             )),
             col((
                 h2("Matching Pages"),
+                sort_links(data),
                 ul(data
                     .pages
                     .iter()
                     .map(|page| {
-                        li(a.href(page.url())(
-                            page.title.clone(),
+                        li((
+                            a.href(page.url())(page.title.clone()),
+                            data.sort_by_modified.as_some(()).map(|()| {
+                                (
+                                    " ",
+                                    page.modified.as_ref().map(time_ago),
+                                )
+                            }),
                         ))
                     })
                     .collect::<Vec<_>>()),
@@ -39,10 +70,27 @@ pub fn page(data: &Data) -> impl Render {
         )),
     );
 
-    let buttons = a
-        .id("new")
-        .class("btn btn-outline-primary mx-1")
-        .href("/~new")("New");
+    let buttons = (
+        a.id("new")
+            .class("btn btn-outline-primary mx-1")
+            .href("/~new")("New"),
+        a.id("feed")
+            .class("btn btn-outline-secondary mx-1")
+            .href(format!("{}?format=atom", data.cur_url))("Feed"),
+        a.id("recent")
+            .class("btn btn-outline-secondary mx-1")
+            .href("/~recent")("Recent"),
+        data.base.can_edit.as_some(
+            a.id("trash")
+                .class("btn btn-outline-secondary mx-1")
+                .href("/~trash")("Trash"),
+        ),
+        data.base.can_edit.as_some(
+            a.id("tags")
+                .class("btn btn-outline-secondary mx-1")
+                .href("/~tags")("Tags"),
+        ),
+    );
 
     base::base_with_js(
         &data.base,
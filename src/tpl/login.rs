@@ -23,6 +23,7 @@ pub fn page(data: &Data) -> impl Render {
                 .method("post")(div
                 .class("input-group")(
                 (
+                base::csrf_input(&data.base),
                 input
                     .id("login-password")
                     .class("form-control")
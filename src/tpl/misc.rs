@@ -2,7 +2,9 @@ use super::base::Data;
 use stpl::html::*;
 use stpl::Render;
 
-use crate::data;
+use crate::{data, tag};
+use chrono::{DateTime, Local};
+use std::collections::HashMap;
 
 pub fn flash(_data: &Data) -> (impl Render, impl Render) {
     (
@@ -37,26 +39,78 @@ pub fn breadcrumb(mut names: Vec<BreadCrumbItem>) -> impl Render {
     ))
 }
 
-pub fn narrowing_tags_col(cur_url: &str, narrowing_tags: &data::NarrowingTagsSet) -> impl Render {
-    if !narrowing_tags.is_empty() {
-        let mut list: Vec<(_, _)> = narrowing_tags.iter().collect();
-        list.sort_by(|n, m| n.0.cmp(m.0));
-        Some((
-            h4("Narrow down"),
-            p(list
-                .iter()
-                .map(|(tag, nums)| {
-                    (
-                        a.href(url_append(cur_url, tag))((
-                            format!("#{}", tag),
+/// Narrowing tags under their closest narrowing ancestor
+struct TagTree<'a> {
+    cur_url: &'a str,
+    narrowing_tags: &'a data::NarrowingTagsSet,
+    /// Parent -> sorted children, `None` for top level tags
+    children: HashMap<Option<&'a str>, Vec<&'a str>>,
+}
+
+impl<'a> TagTree<'a> {
+    fn new(
+        cur_url: &'a str,
+        narrowing_tags: &'a data::NarrowingTagsSet,
+        separator: Option<char>,
+    ) -> Self {
+        let mut children = HashMap::new();
+        for tag in narrowing_tags.keys() {
+            let parent = tag::with_ancestors(tag, separator)
+                .into_iter()
+                .skip(1)
+                .find(|tag| narrowing_tags.contains_key(*tag));
+            children
+                .entry(parent)
+                .or_insert_with(Vec::new)
+                .push(tag.as_str());
+        }
+        for tags in children.values_mut() {
+            tags.sort();
+        }
+        TagTree {
+            cur_url: cur_url,
+            narrowing_tags: narrowing_tags,
+            children: children,
+        }
+    }
+
+    fn list(&self, parent: Option<&'a str>) -> Box<Render> {
+        let tags = match self.children.get(&parent) {
+            Some(tags) => tags,
+            None => return Box::new(()),
+        };
+        Box::new(ul.class("list-unstyled ml-2")(
+            tags.iter()
+                .map(|tag| {
+                    // children show only their part, like `/rust`
+                    let label = match parent {
+                        Some(parent) => {
+                            tag[parent.len()..].to_owned()
+                        }
+                        None => format!("#{}", tag),
+                    };
+                    li((
+                        a.href(url_append(self.cur_url, tag))((
+                            label,
                             nbsp,
-                            format!("({})", nums),
+                            format!("({})", self.narrowing_tags[*tag]),
                         )),
-                        " ",
-                    )
+                        self.list(Some(*tag)),
+                    ))
                 })
-                .collect::<Vec<_>>()),
+                .collect::<Vec<_>>(),
         ))
+    }
+}
+
+pub fn narrowing_tags_col(
+    cur_url: &str,
+    narrowing_tags: &data::NarrowingTagsSet,
+    separator: Option<char>,
+) -> impl Render {
+    if !narrowing_tags.is_empty() {
+        let tree = TagTree::new(cur_url, narrowing_tags, separator);
+        Some((h4("Narrow down"), tree.list(None)))
     } else {
         None
     }
@@ -70,8 +124,11 @@ pub fn broadening_tags_col(_cur_url: &str, mut tags: Vec<String>) -> impl Render
             p(tags
                 .iter()
                 .map(|tag_to_skip| {
-                    let tags_without_skipped_tag: Vec<String> =
-                        tags.iter().filter(|t| *t != tag_to_skip).cloned().collect();
+                    let tags_without_skipped_tag: Vec<String> = tags
+                        .iter()
+                        .filter(|t| *t != tag_to_skip)
+                        .map(|t| tag::to_url_segment(t))
+                        .collect();
                     (
                         a.href("/".to_string() + tags_without_skipped_tag.join("/").as_str())(
                             format!("#{}", tag_to_skip),
@@ -86,6 +143,7 @@ pub fn broadening_tags_col(_cur_url: &str, mut tags: Vec<String>) -> impl Render
     }
 }
 pub fn url_append(base: &str, tag: &str) -> String {
+    let tag = tag::to_url_segment(tag);
     if base.as_bytes().last().cloned() == Some('/' as u8) {
         format!("{}{}/", base, tag)
     } else {
@@ -105,6 +163,31 @@ pub fn breadcrumb_from_tags(tags: &[String]) -> Box<Render> {
     }
 }
 
+/// Like "5 minutes ago"
+pub fn relative_time(time: &DateTime<Local>) -> String {
+    let secs = Local::now().signed_duration_since(*time).num_seconds();
+    let (n, unit) = if secs < 60 {
+        return "just now".into();
+    } else if secs < 60 * 60 {
+        (secs / 60, "minute")
+    } else if secs < 24 * 60 * 60 {
+        (secs / (60 * 60), "hour")
+    } else if secs < 30 * 24 * 60 * 60 {
+        (secs / (24 * 60 * 60), "day")
+    } else if secs < 365 * 24 * 60 * 60 {
+        (secs / (30 * 24 * 60 * 60), "month")
+    } else {
+        (secs / (365 * 24 * 60 * 60), "year")
+    };
+    format!("{} {}{} ago", n, unit, if n == 1 { "" } else { "s" })
+}
+
+/// Relative time, with the exact one on hover
+pub fn time_ago(time: &DateTime<Local>) -> impl Render {
+    span.class("text-muted")
+        .attr("title", time.format("%F %H:%M").to_string())(relative_time(time))
+}
+
 pub fn col<C: Render + 'static>(content: C) -> impl Render {
     div.class("col-9 px-4")(content)
 }
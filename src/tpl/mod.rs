@@ -1,8 +1,17 @@
+pub mod audit;
 pub mod base;
+pub mod broken_links;
+pub mod conflicts;
+pub mod history;
 pub mod index;
 pub mod login;
 pub mod misc;
 pub mod new;
+pub mod recent;
+pub mod search;
+pub mod status;
+pub mod tags;
+pub mod trash;
 pub mod view;
 
 /*
@@ -0,0 +1,44 @@
+use stpl::{
+    html::{a, h2, li, p, ul},
+    Render,
+};
+
+use super::{base, misc::*};
+use crate::page::Page;
+
+#[derive(Clone)]
+pub struct Data<'a> {
+    pub base: base::Data<'a>,
+    /// Most recently modified first
+    pub pages: Vec<Page>,
+}
+
+pub fn page(data: &Data) -> impl Render {
+    let list: Box<dyn Render> = if data.pages.is_empty() {
+        Box::new(p("No pages yet."))
+    } else {
+        Box::new(ul(data
+            .pages
+            .iter()
+            .map(|page| {
+                li((
+                    a.href(page.url())(page.title.clone()),
+                    " ",
+                    page.modified.as_ref().map(time_ago),
+                ))
+            })
+            .collect::<Vec<_>>()))
+    };
+
+    let content = (
+        breadcrumb_from_tags(&["Recent changes".into()]),
+        row((col_menu(()), col((h2("Recent Changes"), list)))),
+    );
+
+    base::base_with_js(
+        &data.base,
+        Box::new(content),
+        Box::new(()),
+        Box::new(()),
+    )
+}
@@ -0,0 +1,69 @@
+use stpl::{
+    html::{a, div, h2, p, small},
+    Render,
+};
+
+use super::{base, misc::*};
+use crate::page::Page;
+
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub page: Page,
+    /// Already escaped html
+    pub snippet: String,
+}
+
+#[derive(Clone)]
+pub struct Data<'a> {
+    pub base: base::Data<'a>,
+    /// Already escaped html
+    pub query: String,
+    pub results: Vec<SearchResult>,
+}
+
+fn result(result: &SearchResult) -> impl Render {
+    div.class("mb-3")((
+        a.href(result.page.url())(result.page.title.clone()),
+        " ",
+        small.class("text-muted")(
+            result
+                .page
+                .tags
+                .iter()
+                .map(|tag| format!("#{}", tag))
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        p(result.snippet.clone()),
+    ))
+}
+
+pub fn page(data: &Data) -> impl Render {
+    let results: Box<dyn Render> = if data.results.is_empty() {
+        Box::new(p("Nothing found."))
+    } else {
+        Box::new(
+            data.results.iter().map(result).collect::<Vec<_>>(),
+        )
+    };
+
+    let content = (
+        breadcrumb_from_tags(&["Search".into()]),
+        row((
+            col_menu(()),
+            col((
+                h2(format!("Search results for: {}", data.query)),
+                results,
+            )),
+        )),
+    );
+
+    let buttons = ();
+
+    base::base_with_js(
+        &data.base,
+        Box::new(content),
+        Box::new(buttons),
+        Box::new(()),
+    )
+}
@@ -0,0 +1,79 @@
+use stpl::{
+    html::{code, h2, h4, li, p, ul},
+    Render,
+};
+
+use super::{base, misc::*};
+use crate::data::LoadStatus;
+
+#[derive(Clone)]
+pub struct Data<'a> {
+    pub base: base::Data<'a>,
+    pub status: LoadStatus,
+}
+
+fn failed_files(status: &LoadStatus) -> Box<dyn Render> {
+    if status.failed_files.is_empty() {
+        return Box::new(p("All files loaded."));
+    }
+
+    let mut list: Vec<_> = status.failed_files.iter().collect();
+    list.sort_by(|n, m| n.0.cmp(m.0));
+    Box::new(ul(list
+        .iter()
+        .map(|(path, msg)| {
+            li((
+                code(path.display().to_string()),
+                ": ",
+                (*msg).clone(),
+            ))
+        })
+        .collect::<Vec<_>>()))
+}
+
+fn recent_errors(status: &LoadStatus) -> Box<dyn Render> {
+    if status.recent_errors.is_empty() {
+        return Box::new(p("No errors."));
+    }
+
+    Box::new(ul(status
+        .recent_errors
+        .iter()
+        .rev()
+        .map(|error| {
+            li((
+                error.time.format("%F %T").to_string(),
+                " ",
+                error.path.as_ref().map(|path| {
+                    (code(path.display().to_string()), ": ")
+                }),
+                error.msg.clone(),
+            ))
+        })
+        .collect::<Vec<_>>()))
+}
+
+pub fn page(data: &Data) -> impl Render {
+    let content = (
+        breadcrumb_from_tags(&["Status".into()]),
+        row((
+            col_menu(()),
+            col((
+                h2("Status"),
+                h4("Files that failed to load"),
+                failed_files(&data.status),
+                h4("Recent errors"),
+                recent_errors(&data.status),
+            )),
+        )),
+    );
+
+    let buttons = ();
+
+    base::base_with_js(
+        &data.base,
+        Box::new(content),
+        Box::new(buttons),
+        Box::new(()),
+    )
+}
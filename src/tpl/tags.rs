@@ -0,0 +1,130 @@
+use stpl::{
+    html::{button, code, div, form, h2, h4, input, p, pre},
+    Render,
+};
+
+use super::{base, misc::*};
+use crate::search::escape_html;
+
+use boolinator::Boolinator;
+
+/// What renaming changes in one page file
+#[derive(Clone, Debug)]
+pub struct FileDiff {
+    /// Path relative to the data dir
+    pub file_name: String,
+    pub diff: String,
+}
+
+#[derive(Clone)]
+pub struct Data<'a> {
+    pub base: base::Data<'a>,
+    pub old: String,
+    pub new: String,
+    pub merge: bool,
+    pub error: Option<String>,
+    /// `None` until previewed
+    pub diffs: Option<Vec<FileDiff>>,
+    /// `rename::plan_hash` of `diffs`, required to apply them
+    pub plan_hash: Option<String>,
+    /// Whether `diffs` were written already
+    pub applied: bool,
+}
+
+fn rename_form(data: &Data) -> impl Render {
+    let merge = input
+        .class("form-check-input")
+        .attr("type", "checkbox")
+        .id("merge")
+        .name("merge")
+        .value("true");
+    let merge: Box<dyn Render> = if data.merge {
+        Box::new(merge.attr("checked", "checked"))
+    } else {
+        Box::new(merge)
+    };
+
+    form.class("form-inline mb-3").action("/~tags").method("post")((
+        base::csrf_input(&data.base),
+        data.plan_hash.as_ref().map(|plan_hash| {
+            input
+                .attr("type", "hidden")
+                .name("plan_hash")
+                .value(plan_hash.clone())
+        }),
+        input
+            .class("form-control mr-2")
+            .attr("type", "text")
+            .name("old")
+            .placeholder("old-tag")
+            .value(escape_html(&data.old)),
+        input
+            .class("form-control mr-2")
+            .attr("type", "text")
+            .name("new")
+            .placeholder("new-tag")
+            .value(escape_html(&data.new)),
+        div.class("form-check mr-2")((
+            merge,
+            " Merge into an existing tag",
+        )),
+        button
+            .type_("submit")
+            .class("btn btn-outline-secondary mr-2")("Preview"),
+        button
+            .type_("submit")
+            .name("apply")
+            .value("true")
+            .class("btn btn-outline-danger")("Apply"),
+    ))
+}
+
+fn file_diff(file_diff: &FileDiff) -> impl Render {
+    div.class("mb-3")((
+        h4(code(escape_html(&file_diff.file_name))),
+        pre(escape_html(&file_diff.diff)),
+    ))
+}
+
+pub fn page(data: &Data) -> impl Render {
+    // a refused apply still shows the current preview
+    let error = data.error.as_ref().map(|error| {
+        div.class("alert alert-danger")(escape_html(error))
+    });
+    let result: Box<dyn Render> = match &data.diffs {
+        Some(diffs) if diffs.is_empty() => {
+            Box::new(p("No page mentions this tag."))
+        }
+        Some(diffs) => Box::new((
+            p(format!(
+                "{} {} files:",
+                if data.applied { "Changed" } else { "Would change" },
+                diffs.len()
+            )),
+            diffs.iter().map(file_diff).collect::<Vec<_>>(),
+        )),
+        None => Box::new(()),
+    };
+
+    let content = (
+        breadcrumb_from_tags(&["Tags".into()]),
+        row((
+            col_menu(()),
+            col((
+                h2("Rename Tag"),
+                p("Rewrites #tags outside of code, and internal \
+                   links using them, in every page."),
+                data.base.can_edit.as_some(rename_form(data)),
+                error,
+                result,
+            )),
+        )),
+    );
+
+    base::base_with_js(
+        &data.base,
+        Box::new(content),
+        Box::new(()),
+        Box::new(()),
+    )
+}
@@ -0,0 +1,66 @@
+use stpl::{
+    html::{button, form, h2, input, li, p, ul},
+    Render,
+};
+
+use super::{base, misc::*};
+use crate::data::TrashedPage;
+
+#[derive(Clone)]
+pub struct Data<'a> {
+    pub base: base::Data<'a>,
+    pub cur_url: String,
+    pub pages: Vec<TrashedPage>,
+}
+
+fn restore_form(
+    data: &base::Data,
+    trashed: &TrashedPage,
+) -> impl Render {
+    form.class("d-inline")
+        .action("/~trash/restore")
+        .method("post")((
+        base::csrf_input(data),
+        input
+            .attr("type", "hidden")
+            .name("name")
+            .value(trashed.file_name.clone()),
+        button
+            .type_("submit")
+            .class("btn btn-sm btn-outline-success mx-1")(
+            "Restore"
+        ),
+    ))
+}
+
+pub fn page(data: &Data) -> impl Render {
+    let list: Box<dyn Render> = if data.pages.is_empty() {
+        Box::new(p("Trash is empty."))
+    } else {
+        Box::new(ul(data
+            .pages
+            .iter()
+            .map(|trashed| {
+                li((
+                    trashed.page.title.clone(),
+                    " ",
+                    restore_form(&data.base, trashed),
+                ))
+            })
+            .collect::<Vec<_>>()))
+    };
+
+    let content = (
+        breadcrumb_from_tags(&["Trash".into()]),
+        row((col_menu(()), col((h2("Trashed Pages"), list)))),
+    );
+
+    let buttons = ();
+
+    base::base_with_js(
+        &data.base,
+        Box::new(content),
+        Box::new(buttons),
+        Box::new(()),
+    )
+}
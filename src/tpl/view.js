@@ -1,9 +1,60 @@
 var editor;
+// the page version the editor started from, and its hash
+var baseText;
+var baseHash;
+
+// Line diff of `a` and `b`, based on their longest common subsequence
+function lineDiff(a, b) {
+  const x = a.split('\n');
+  const y = b.split('\n');
+  const lcs = [];
+  for (var i = x.length; i >= 0; i--) {
+    lcs[i] = [];
+    for (var j = y.length; j >= 0; j--) {
+      if (i == x.length || j == y.length) {
+        lcs[i][j] = 0;
+      } else if (x[i] == y[j]) {
+        lcs[i][j] = lcs[i + 1][j + 1] + 1;
+      } else {
+        lcs[i][j] = Math.max(lcs[i + 1][j], lcs[i][j + 1]);
+      }
+    }
+  }
+  const out = [];
+  var i = 0, j = 0;
+  while (i < x.length || j < y.length) {
+    if (i < x.length && j < y.length && x[i] == y[j]) {
+      out.push('  ' + x[i]);
+      i++; j++;
+    } else if (j == y.length || (i < x.length && lcs[i + 1][j] >= lcs[i][j + 1])) {
+      out.push('- ' + x[i]);
+      i++;
+    } else {
+      out.push('+ ' + y[j]);
+      j++;
+    }
+  }
+  return out.join('\n');
+}
+
+function showEditConflict(conflict) {
+  $("#edit_conflict_diff").text(lineDiff(conflict.current, editor.getValue()));
+  $("#edit_conflict").show();
+  if (conflict.merged) {
+    // merged text with conflict markers, for resolving in place
+    editor.setValue(conflict.merged, -1);
+  }
+  // saving again overwrites the version shown in the diff
+  baseText = conflict.current;
+  baseHash = conflict.hash;
+}
 
 $("#edit").click(function(){
   $("#view_tab").hide();
   $("#edit").hide();
   $("#new").hide();
+  $("#delete").hide();
+  $("#history").hide();
   $("#edit_tab").show();
   $("#save").show();
   editor.focus();
@@ -16,7 +67,9 @@ $("#save").click(function(){
     type: 'PUT',
     url: window.location.href,
     data: JSON.stringify({
-      text: text
+      text: text,
+      hash: baseHash,
+      base: baseText
     }),
     success: function(data) {
       if (data.redirect) {
@@ -26,7 +79,9 @@ $("#save").click(function(){
       }
     },
     error:function(data) {
-      if (data.status == 409) {
+      if (data.status == 409 && data.responseJSON && data.responseJSON.current !== undefined) {
+        showEditConflict(data.responseJSON);
+      } else if (data.status == 409) {
         alert('Conflict. Try more tags.');
       } else if (data.status == 404) {
         alert('Not found.');
@@ -38,8 +93,31 @@ $("#save").click(function(){
     dataType: 'json'
   });
 });
+$("#delete").click(function(){
+  if (!confirm('Move this page to trash?')) {
+    return;
+  }
+  $.ajax({
+    type: 'DELETE',
+    url: window.location.href,
+    success: function(data) {
+      window.location = data.redirect || '/';
+    },
+    error:function(data) {
+      if (data.status == 401) {
+        alert('Unauthorized.');
+      } else {
+        alert('Unknown error. Status: ' + data.status);
+      }
+    },
+    dataType: 'json'
+  });
+});
+
 $(document).ready(function() {
+  baseHash = $("#editor").attr("data-hash");
   editor = ace.edit("editor");
+  baseText = editor.getValue();
   editor.setTheme("ace/theme/textmate");
   editor.session.setMode("ace/mode/markdown");
   editor.setKeyboardHandler("ace/keyboard/vim");
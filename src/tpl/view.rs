@@ -1,13 +1,14 @@
 use stpl::{
-    html::{a, button, div, raw, script},
+    html::{a, button, div, h4, li, p, pre, raw, script, ul},
     Render,
 };
 
 use super::{
     base,
+    conflicts::{self, PageFile},
     misc::{self, *},
 };
-use crate::{data, page::Page};
+use crate::{data, page::Page, search::escape_html};
 
 #[derive(Clone)]
 pub struct Data<'a> {
@@ -15,6 +16,59 @@ pub struct Data<'a> {
     pub page: Page,
     pub cur_url: String,
     pub narrowing_tags: data::NarrowingTagsSet,
+    /// Pages linking to this one
+    pub backlinks: Vec<Page>,
+    /// Other pages with exactly the same tags
+    pub conflicts: Vec<PageFile>,
+}
+
+fn conflicts_alert(data: &Data) -> impl Render {
+    if data.conflicts.is_empty() {
+        return None;
+    }
+    Some(div.class("alert alert-warning")((
+        "Other pages have exactly the same tags, add a tag to tell them apart:",
+        ul(data
+            .conflicts
+            .iter()
+            .map(|page_file| conflicts::page_file_item(&data.base, page_file))
+            .collect::<Vec<_>>()),
+    )))
+}
+
+fn backlinks(pages: &[Page]) -> impl Render {
+    if pages.is_empty() {
+        None
+    } else {
+        Some(div.id("backlinks").class("mt-4")((
+            h4("Linked from"),
+            ul(pages
+                .iter()
+                .map(|page| li(a.href(page.url())(page.title.clone())))
+                .collect::<Vec<_>>()),
+        )))
+    }
+}
+
+/// Tags implied through `tags.toml`, apart from the written ones
+fn derived_tags(page: &Page) -> impl Render {
+    if page.derived_tags.is_empty() {
+        None
+    } else {
+        Some((
+            h4("Implied tags"),
+            p(page
+                .derived_tags
+                .iter()
+                .map(|tag| {
+                    (
+                        a.href(url_append("/", tag))(format!("#{}", tag)),
+                        " ",
+                    )
+                })
+                .collect::<Vec<_>>()),
+        ))
+    }
 }
 
 pub fn page(data: &Data) -> impl Render {
@@ -25,19 +79,35 @@ pub fn page(data: &Data) -> impl Render {
                 misc::narrowing_tags_col(
                     &data.cur_url,
                     &data.narrowing_tags,
+                    data.base.site_settings.tags.hierarchy_separator,
                 ),
                 misc::broadening_tags_col(
                     &data.cur_url,
                     data.page.tags.clone(),
                 ),
+                derived_tags(&data.page),
             )),
             col((
-                div.id("view_tab")((data.page.html.clone(),)),
+                div.id("view_tab")((
+                    conflicts_alert(data),
+                    data.page.html.clone(),
+                    backlinks(&data.backlinks),
+                )),
                 div.id("edit_tab")
                     .attr("style", "display: none;")(
                     (
-                    div.id("editor").class("my-2")(
-                        data.page.md.clone(),
+                    div.id("edit_conflict")
+                        .class("alert alert-warning")
+                        .attr("style", "display: none;")((
+                        "The page was changed since you started editing, and the changes conflict. Conflicting lines are marked in the editor; lines only in the current version are marked with - below, lines only in yours with +. Resolve them and save again.",
+                        pre.id("edit_conflict_diff").class("mt-2 mb-0")(""),
+                    )),
+                    div.id("editor")
+                        .class("my-2")
+                        .attr("data-hash", data.page.md_hash())(
+                        // escaped, so the editor gets back exactly `md`
+                        // and its hash matches `data-hash`
+                        escape_html(&data.page.md),
                     ),
                 )
                 ),
@@ -72,6 +142,17 @@ pub fn page(data: &Data) -> impl Render {
             .class("btn btn-outline-primary mx-1")(
             "Save"
         ),
+        a.id("history")
+            .class("btn btn-outline-secondary mx-1")
+            .href(String::from("/~history") + data.page.url().as_str())(
+            "History"
+        ),
+        button
+            .id("delete")
+            .type_("submit")
+            .class("btn btn-outline-danger mx-1")(
+            "Delete"
+        ),
     );
 
     let js = (
@@ -8,10 +8,25 @@
 //    if the page with the same tags exists, return error
 // PUT /a/b/c - update page
 //    if the page with the same tags exists, return error
+//    if the page changed since the editor loaded it, merge
+//    the changes, return error with the current text if they
+//    conflict
 //
-// DELETE /id - delete page
+// DELETE /a/b/c - move page to trash
+//
+// GET /a/-b, /~q/(a|b)+c - boolean tag query, see `query`
+//
+// GET /~feed/a/b/, /a/b/?format=atom, /a/-b?format=atom - Atom feed
+//    of pages matching tags or a query
+//
+// GET with `Accept: application/json` or ?format=json - the match
+//    as JSON, see `api`
 //
 // POST /~login login
+//
+// Every state-changing request has to carry the session's CSRF
+// token: forms in the `csrf_token` field, scripts in the
+// `X-CSRF-Token` header. API token requests are exempt.
 // ANY /~... other special stuff
 
 use actix_web::{
@@ -27,8 +42,16 @@ use actix_web::{
     HttpRequest, HttpResponse, Query, Responder, Result,
 };
 
-use crate::settings::Site;
-use std::sync::Arc;
+use crate::settings::{self, Site, TokenScope};
+use log::warn;
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
+use url::percent_encoding::{
+    percent_decode, utf8_percent_encode, DEFAULT_ENCODE_SET,
+};
 use stpl::html::RenderExt;
 
 const LOGGED_IN_COOKIE_NAME: &str = "logged_in";
@@ -36,9 +59,18 @@ const LOGGED_IN_COOKIE_NAME: &str = "logged_in";
 use futures::Future;
 
 use crate::{
+    api,
+    audit::AuditLog,
+    auth::{self, Access},
+    check,
     data::{self, MatchType, PageId},
+    feed,
+    markdown, merge,
     opts::Opts,
-    page::Page,
+    page::{self, Page},
+    query, rename,
+    search, tag,
+    throttle::LoginThrottle,
     tpl,
 };
 
@@ -46,6 +78,10 @@ use crate::{
 enum UserError {
     #[fail(display = "Unauthorized")]
     Unauthorized,
+    #[fail(display = "Forbidden")]
+    Forbidden,
+    #[fail(display = "Missing or invalid CSRF token")]
+    InvalidCsrfToken,
 }
 
 type UserResult<T> = std::result::Result<T, UserError>;
@@ -56,19 +92,16 @@ impl error::ResponseError for UserError {
             UserError::Unauthorized => {
                 HttpResponse::new(http::StatusCode::UNAUTHORIZED)
             }
+            UserError::Forbidden => {
+                HttpResponse::new(http::StatusCode::FORBIDDEN)
+            }
+            UserError::InvalidCsrfToken => {
+                HttpResponse::Forbidden().body(self.to_string())
+            }
         }
     }
 }
 
-fn can_edit(req: &HttpRequest<State>) -> Result<bool> {
-    let local = req.state().opts.local;
-    let logged_in = req
-        .session()
-        .get::<bool>(LOGGED_IN_COOKIE_NAME)?
-        .unwrap_or(false);
-    Ok(local || logged_in)
-}
-
 fn can_login(req: &HttpRequest<State>) -> bool {
     let local = req.state().opts.local;
     let password_set =
@@ -131,6 +164,14 @@ fn login_get(req: HttpRequest<State>) -> Result<HttpResponse> {
 #[derive(Deserialize, Debug, Clone)]
 struct PasswordForm {
     password: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct CsrfForm {
+    #[serde(default)]
+    csrf_token: String,
 }
 
 fn login_post(
@@ -139,27 +180,197 @@ fn login_post(
     let hashed_password =
         req.state().site_settings.hashed_password.clone();
     let local = req.state().opts.local;
+    let expected_csrf_token = expected_csrf_token(&req);
+    let throttle = req.state().login_throttle.clone();
+    let auditor = Auditor::new(&req);
 
     Form::<PasswordForm>::extract(&req)
         .and_then(move |form| {
-            if local || hashed_password.is_none() {
-                Ok(redirect_to_303("/"))
-            } else if libpasta::verify_password(
-                &hashed_password.unwrap(),
+            verify_csrf_token(&expected_csrf_token, &form.csrf_token)?;
+            let hashed_password = match hashed_password {
+                Some(ref hashed_password) if !local => hashed_password,
+                _ => return Ok(redirect_to_303("/")),
+            };
+
+            if let Some(wait) =
+                throttle.check(&auditor.addr, Instant::now())
+            {
+                auditor.record("Login blocked");
+                let secs = wait.as_secs().max(1);
+                return Ok(HttpResponse::build(
+                    StatusCode::TOO_MANY_REQUESTS,
+                )
+                .header(http::header::RETRY_AFTER, secs.to_string())
+                .body(format!(
+                    "Too many failed logins, try again in {} seconds",
+                    secs
+                )));
+            }
+
+            if libpasta::verify_password(
+                hashed_password,
                 form.password.clone(),
             ) {
+                throttle.record_success(&auditor.addr);
                 req.session().set(LOGGED_IN_COOKIE_NAME, true)?;
+                auditor.record("Login");
                 Ok(redirect_to_303("/"))
             } else {
+                throttle.record_failure(&auditor.addr, Instant::now());
+                auditor.record("Failed login");
                 Ok(redirect_to_303("/~login"))
             }
         })
         .responder()
 }
 
-fn logout(req: HttpRequest<State>) -> Result<HttpResponse> {
-    req.session().remove(LOGGED_IN_COOKIE_NAME);
-    Ok(redirect_to_303("/"))
+fn logout(
+    req: HttpRequest<State>,
+) -> Box<Future<Item = HttpResponse, Error = error::Error>> {
+    let expected_csrf_token = expected_csrf_token(&req);
+    let auditor = Auditor::new(&req);
+
+    Form::<CsrfForm>::extract(&req)
+        .and_then(move |form| {
+            verify_csrf_token(&expected_csrf_token, &form.csrf_token)?;
+            req.session().remove(LOGGED_IN_COOKIE_NAME);
+            auditor.record("Logout");
+            Ok(redirect_to_303("/"))
+        })
+        .responder()
+}
+
+/// Address of the client, from `client_ip_header` if configured
+///
+/// Only the last entry of the header is used, the one added by the
+/// proxy itself; clients can put anything before it.
+fn client_addr(req: &HttpRequest<State>) -> String {
+    let from_header =
+        req.state().site_settings.client_ip_header.as_ref().and_then(
+            |name| {
+                let value = req.headers().get(name.as_str())?;
+                let last = value.to_str().ok()?.rsplit(',').next()?;
+                Some(last.trim().to_owned())
+                    .filter(|addr| !addr.is_empty())
+            },
+        );
+    from_header
+        .or_else(|| req.peer_addr().map(|addr| addr.ip().to_string()))
+        .unwrap_or_else(|| "unknown".into())
+}
+
+/// Client address and identity for the audit log, taken before the
+/// request body is read
+#[derive(Clone)]
+struct Auditor {
+    log: Arc<AuditLog>,
+    addr: String,
+    who: String,
+}
+
+impl Auditor {
+    fn new(req: &HttpRequest<State>) -> Self {
+        let token_name = bearer_token(req)
+            .and_then(|token| req.state().site_settings.find_token(token))
+            .map(|token| token.name.clone());
+        let logged_in = req
+            .session()
+            .get::<bool>(LOGGED_IN_COOKIE_NAME)
+            .unwrap_or(None)
+            .unwrap_or(false);
+        let who = match token_name {
+            Some(name) => format!("token {}", name),
+            None if logged_in => "session".into(),
+            None if req.state().opts.local => "local".into(),
+            None => "anonymous".into(),
+        };
+
+        Auditor {
+            log: req.state().audit.clone(),
+            addr: client_addr(req),
+            who: who,
+        }
+    }
+
+    fn record(&self, action: &str) {
+        if let Err(e) = self.log.record(&self.addr, &self.who, action) {
+            warn!("Failed to write audit log: {}", e);
+        }
+    }
+}
+
+const CSRF_TOKEN_SESSION_KEY: &str = "csrf_token";
+const CSRF_TOKEN_HEADER: &str = "X-CSRF-Token";
+
+/// This session's token for forms and scripts, created on first use
+fn csrf_token(req: &HttpRequest<State>) -> Result<String> {
+    if let Some(token) =
+        req.session().get::<String>(CSRF_TOKEN_SESSION_KEY)?
+    {
+        return Ok(token);
+    }
+
+    use ring::rand::SecureRandom;
+    let mut bytes = [0u8; 32];
+    ring::rand::SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| error::ErrorInternalServerError("No randomness"))?;
+    let token = hex::encode(&bytes);
+    req.session().set(CSRF_TOKEN_SESSION_KEY, token.clone())?;
+    Ok(token)
+}
+
+/// Token a state-changing request has to carry, `None` if it needs
+/// none
+///
+/// Browsers never add a Bearer token on their own, so requests with
+/// a valid API token can't be forged. Other `Authorization` headers,
+/// like Basic credentials for a reverse proxy, are resent by the
+/// browser with forged requests too and don't count.
+fn expected_csrf_token(req: &HttpRequest<State>) -> Option<String> {
+    let valid_token = bearer_token(req)
+        .and_then(|token| req.state().site_settings.find_token(token))
+        .is_some();
+    if valid_token {
+        return None;
+    }
+    Some(
+        req.session()
+            .get::<String>(CSRF_TOKEN_SESSION_KEY)
+            .unwrap_or(None)
+            .unwrap_or_default(),
+    )
+}
+
+fn verify_csrf_token(
+    expected: &Option<String>,
+    given: &str,
+) -> UserResult<()> {
+    let expected = match *expected {
+        Some(ref expected) => expected,
+        None => return Ok(()),
+    };
+    if !expected.is_empty()
+        && ring::constant_time::verify_slices_are_equal(
+            expected.as_bytes(),
+            given.as_bytes(),
+        )
+        .is_ok()
+    {
+        Ok(())
+    } else {
+        Err(UserError::InvalidCsrfToken)
+    }
+}
+
+/// Check the token scripts send in the `X-CSRF-Token` header
+fn check_csrf_header(req: &HttpRequest<State>) -> UserResult<()> {
+    let given = req
+        .headers()
+        .get(CSRF_TOKEN_HEADER)
+        .and_then(|given| given.to_str().ok())
+        .unwrap_or("");
+    verify_csrf_token(&expected_csrf_token(req), given)
 }
 
 fn redirect_to(location: &str) -> HttpResponse {
@@ -182,28 +393,86 @@ struct PostResponse {
     redirect: String,
 }
 
-fn assert_is_authorized(
+/// Token in the `Authorization: Bearer` header
+fn bearer_token(req: &HttpRequest<State>) -> Option<&str> {
+    req.headers()
+        .get(http::header::AUTHORIZATION)?
+        .to_str()
+        .ok()
+        .and_then(|header| {
+            if header.starts_with("Bearer ") {
+                Some(header["Bearer ".len()..].trim())
+            } else {
+                None
+            }
+        })
+}
+
+/// Scope of the API token in the `Authorization: Bearer` header
+///
+/// An unknown token is an error, not an anonymous request. Other
+/// authorization schemes, like Basic for a reverse proxy, aren't
+/// meant for the wiki and count as no token.
+fn bearer_token_scope(
     req: &HttpRequest<State>,
-) -> UserResult<()> {
-    if !req
-        .session()
-        .get::<bool>(LOGGED_IN_COOKIE_NAME)
-        .unwrap_or(None)
-        .unwrap_or(false)
-    {
-        return Err(UserError::Unauthorized);
+) -> UserResult<Option<TokenScope>> {
+    let token = match bearer_token(req) {
+        Some(token) => token,
+        None => return Ok(None),
+    };
+
+    req.state()
+        .site_settings
+        .find_token(token)
+        .map(|token| Some(token.scope))
+        .ok_or(UserError::Unauthorized)
+}
+
+impl FromRequest<State> for Access {
+    type Config = ();
+    type Result = Result<Self>;
+
+    fn from_request(
+        req: &HttpRequest<State>,
+        _cfg: &Self::Config,
+    ) -> Self::Result {
+        let credentials = auth::Credentials {
+            local: req.state().opts.local,
+            logged_in: req
+                .session()
+                .get::<bool>(LOGGED_IN_COOKIE_NAME)?
+                .unwrap_or(false),
+            token: bearer_token_scope(req)?,
+        };
+        Ok(auth::access(&credentials))
     }
+}
 
-    Ok(())
+/// Fail unless the request has at least `required` access
+fn require(
+    req: &HttpRequest<State>,
+    required: Access,
+) -> UserResult<()> {
+    let access = Access::extract(req)
+        .map_err(|_| UserError::Unauthorized)?;
+    if access >= required {
+        Ok(())
+    } else if access == Access::Anonymous {
+        Err(UserError::Unauthorized)
+    } else {
+        Err(UserError::Forbidden)
+    }
 }
 
 fn post(
     req: HttpRequest<State>,
 ) -> Result<Box<Future<Item = HttpResponse, Error = error::Error>>>
 {
-    assert_is_authorized(&req)?;
+    require(&req, Access::Write)?;
+    check_csrf_header(&req)?;
     let data = req.state().data.clone();
     let data_dir = req.state().opts.data_dir.clone();
+    let auditor = Auditor::new(&req);
 
     Ok(req
         .json()
@@ -211,8 +480,10 @@ fn post(
         .and_then(move |input: PostInput| {
             let data_read = data.read();
 
-            let new_page =
-                Page::from_markdown(input.text.clone());
+            let new_page = Page::from_markdown(
+                input.text.clone(),
+                data_read.tag_grammar(),
+            );
 
             let lookup =
                 data_read.lookup_exact(new_page.tags.clone());
@@ -224,6 +495,7 @@ fn post(
             drop(data_read);
 
             data.write_new_file(&new_page, data_dir.as_path())?;
+            auditor.record(&format!("Create {}", new_page.url()));
             Ok(HttpResponse::Ok().json(PostResponse {
                 redirect: new_page.to_full_url(true),
             }))
@@ -238,6 +510,14 @@ fn post(
 #[derive(Debug, Serialize, Deserialize)]
 struct PutInput {
     text: String,
+    /// `Page::md_hash` of the version the editor started from;
+    /// when missing, the page is overwritten unconditionally
+    #[serde(default)]
+    hash: Option<String>,
+    /// Text the editor started from, to merge with the current one
+    /// if they differ
+    #[serde(default)]
+    base: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -245,14 +525,28 @@ struct PutResponse {
     redirect: String,
 }
 
+/// Sent with 409 when the page changed since the editor loaded it
+#[derive(Debug, Serialize, Deserialize)]
+struct PutConflictResponse {
+    /// Current text of the page
+    current: String,
+    /// Hash to send to overwrite `current`
+    hash: String,
+    /// Result of merging with `current`, with conflict markers
+    merged: Option<String>,
+}
+
 fn put(
     req: HttpRequest<State>,
 ) -> UserResult<
     Box<Future<Item = HttpResponse, Error = error::Error>>,
 > {
-    assert_is_authorized(&req)?;
+    require(&req, Access::Write)?;
+    check_csrf_header(&req)?;
     let cur_url = req.path().to_owned();
+    let state = req.state().clone();
     let data = req.state().data.clone();
+    let auditor = Auditor::new(&req);
 
     Ok(req
         .json()
@@ -262,15 +556,53 @@ fn put(
         })
         .from_err()
         .and_then(move |input: PutInput| {
-            let (url_tags, _) = url_to_tags(cur_url.as_str());
-            let data_read = data.read();
-            let page_id = data_read.lookup(url_tags)?;
+            let (page_id, existing_path) =
+                state.page_for_url(&cur_url)?;
+
+            // compare with the file itself, the watcher might
+            // not have picked up the latest change yet
+            let _edit_lock = data.lock_edits();
+            let grammar = data.read().tag_grammar().clone();
+            let current_page =
+                Page::read_from_file(&existing_path, &grammar)?;
+            let current_hash = current_page.md_hash();
+            let text = match input.hash {
+                Some(ref hash) if *hash != current_hash => {
+                    let merged = match input.base {
+                        Some(ref base) if page::md_hash(base) == *hash => {
+                            Some(merge::merge(
+                                base,
+                                &current_page.md,
+                                &input.text,
+                            ))
+                        }
+                        _ => None,
+                    };
+                    match merged {
+                        Some(ref merged) if merged.is_clean() => {
+                            merged.text.clone()
+                        }
+                        merged => {
+                            return Ok(HttpResponse::Conflict().json(
+                                PutConflictResponse {
+                                    current: current_page.md,
+                                    hash: current_hash,
+                                    merged: merged
+                                        .map(|merged| merged.text),
+                                },
+                            ))
+                        }
+                    }
+                }
+                _ => input.text.clone(),
+            };
 
-            let new_page =
-                Page::from_markdown(input.text.clone());
+            let new_page = Page::from_markdown(text, &grammar);
 
+            let data_read = data.read();
             let lookup =
                 data_read.lookup_exact(new_page.tags.clone());
+            drop(data_read);
 
             if lookup == data::LookupOutcome::Many {
                 return Ok(HttpResponse::Conflict().body("{}"));
@@ -282,15 +614,8 @@ fn put(
                 }
             }
 
-            let existing_path = data_read
-                .path_by_id
-                .get(&page_id)
-                .unwrap()
-                .clone();
-
-            drop(data_read);
-
             data.replace_file(&existing_path, &new_page)?;
+            auditor.record(&format!("Update {}", new_page.url()));
 
             Ok(HttpResponse::Ok().json(PutResponse {
                 redirect: new_page.to_full_url(true),
@@ -303,6 +628,409 @@ fn put(
         .responder())
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct DeleteResponse {
+    redirect: String,
+}
+
+fn delete(req: HttpRequest<State>) -> Result<HttpResponse> {
+    require(&req, Access::Write)?;
+    check_csrf_header(&req)?;
+    let data = req.state().data.clone();
+    let data_dir = req.state().opts.data_dir.clone();
+
+    let (_, existing_path) = req.state().page_for_url(req.path())?;
+    data.move_to_trash(&existing_path, data_dir.as_path())?;
+    Auditor::new(&req).record(&format!("Delete {}", req.path()));
+
+    Ok(HttpResponse::Ok().json(DeleteResponse {
+        redirect: "/".into(),
+    }))
+}
+
+fn trash_get(req: HttpRequest<State>) -> Result<HttpResponse> {
+    require(&req, Access::Read)?;
+    let cur_url = req.path();
+    let pages = data::list_trash(
+        &req.state().opts.data_dir,
+        req.state().data.read().tag_grammar(),
+    )?;
+
+    let mut base = tpl::base::Data::from(&req);
+    base.title = "Trash".into();
+    let body = tpl::trash::page(&tpl::trash::Data {
+        base: base,
+        cur_url: cur_url.into(),
+        pages: pages,
+    });
+    Ok(HttpResponse::Ok().body(body.render_to_vec()))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct RestoreForm {
+    name: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+fn trash_restore_post(
+    req: HttpRequest<State>,
+) -> Result<Box<Future<Item = HttpResponse, Error = error::Error>>>
+{
+    require(&req, Access::Write)?;
+    let data = req.state().data.clone();
+    let data_dir = req.state().opts.data_dir.clone();
+    let expected_csrf_token = expected_csrf_token(&req);
+    let auditor = Auditor::new(&req);
+
+    Ok(Form::<RestoreForm>::extract(&req)
+        .and_then(move |form| {
+            verify_csrf_token(&expected_csrf_token, &form.csrf_token)?;
+            let url = data.restore_from_trash(
+                form.name.as_str(),
+                data_dir.as_path(),
+            )?;
+            auditor.record(&format!("Restore {}", url));
+            Ok(redirect_to_303(url.as_str()))
+        })
+        .responder())
+}
+
+const AUDIT_ENTRIES: usize = 500;
+
+fn audit_get(req: HttpRequest<State>) -> Result<HttpResponse> {
+    require(&req, Access::Write)?;
+    let entries = req.state().audit.recent(AUDIT_ENTRIES)?;
+
+    let mut base = tpl::base::Data::from(&req);
+    base.title = "Audit log".into();
+    let body = tpl::audit::page(&tpl::audit::Data {
+        base: base,
+        entries: entries,
+    });
+    Ok(HttpResponse::Ok().body(body.render_to_vec()))
+}
+
+fn status_get(req: HttpRequest<State>) -> Result<HttpResponse> {
+    require(&req, Access::Read)?;
+    let status = req.state().data.read().load_status.clone();
+
+    let mut base = tpl::base::Data::from(&req);
+    base.title = "Status".into();
+    let body = tpl::status::page(&tpl::status::Data {
+        base: base,
+        status: status,
+    });
+    Ok(HttpResponse::Ok().body(body.render_to_vec()))
+}
+
+fn file_get(req: HttpRequest<State>) -> Result<HttpResponse> {
+    let path = req
+        .match_info()
+        .get("path")
+        .and_then(|encoded| req.state().file_url_to_path(encoded));
+    let data = req.state().data.read();
+    let page_id = path
+        .as_ref()
+        .and_then(|path| data.pages_by_path.get(path))
+        .cloned();
+
+    match page_id {
+        Some(page_id) => get_page(
+            &req,
+            &*data,
+            page_id,
+            req.path(),
+            Default::default(),
+        ),
+        None => Ok(HttpResponse::NotFound().body("Not Found :(")),
+    }
+}
+
+fn conflicts_get(req: HttpRequest<State>) -> Result<HttpResponse> {
+    require(&req, Access::Read)?;
+    let data = req.state().data.read();
+    let conflicts = data
+        .conflicts()
+        .into_iter()
+        .map(|ids| {
+            ids.into_iter()
+                .map(|id| req.state().page_file(&*data, id))
+                .collect()
+        })
+        .collect();
+
+    let mut base = tpl::base::Data::from(&req);
+    base.title = "Conflicts".into();
+    let body = tpl::conflicts::page(&tpl::conflicts::Data {
+        base: base,
+        conflicts: conflicts,
+    });
+    Ok(HttpResponse::Ok().body(body.render_to_vec()))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct AddTagForm {
+    /// `/~file/...` url of the page
+    file: String,
+    tag: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+fn conflicts_add_tag_post(
+    req: HttpRequest<State>,
+) -> Result<Box<Future<Item = HttpResponse, Error = error::Error>>>
+{
+    require(&req, Access::Write)?;
+    let state = req.state().clone();
+    let expected_csrf_token = expected_csrf_token(&req);
+    let auditor = Auditor::new(&req);
+
+    Ok(Form::<AddTagForm>::extract(&req)
+        .and_then(move |form| {
+            verify_csrf_token(&expected_csrf_token, &form.csrf_token)?;
+            let path = form
+                .file
+                .trim_left_matches("/~file/")
+                .to_owned();
+            let path = match state.file_url_to_path(&path) {
+                Some(path) => path,
+                None => {
+                    return Ok(HttpResponse::NotFound()
+                        .body("Not Found :("))
+                }
+            };
+            let page =
+                state.data.add_tag(&path, form.tag.trim())?;
+            auditor.record(&format!(
+                "Add tag {} to {}",
+                form.tag.trim(),
+                page.url()
+            ));
+            Ok(redirect_to_303(page.to_full_url(true).as_str()))
+        })
+        .responder())
+}
+
+fn tags_get(req: HttpRequest<State>) -> Result<HttpResponse> {
+    require(&req, Access::Read)?;
+
+    let mut base = tpl::base::Data::from(&req);
+    base.title = "Tags".into();
+    let body = tpl::tags::page(&tpl::tags::Data {
+        base: base,
+        old: String::new(),
+        new: String::new(),
+        merge: false,
+        error: None,
+        diffs: None,
+        plan_hash: None,
+        applied: false,
+    });
+    Ok(HttpResponse::Ok().body(body.render_to_vec()))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct RenameTagForm {
+    old: String,
+    new: String,
+    #[serde(default)]
+    merge: bool,
+    /// Write the changes instead of previewing them
+    #[serde(default)]
+    apply: bool,
+    /// `rename::plan_hash` of the previewed changes
+    #[serde(default)]
+    plan_hash: String,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+fn tags_post(
+    req: HttpRequest<State>,
+) -> Result<Box<Future<Item = HttpResponse, Error = error::Error>>>
+{
+    require(&req, Access::Write)?;
+    let state = req.state().clone();
+    let expected_csrf_token = expected_csrf_token(&req);
+    let auditor = Auditor::new(&req);
+
+    Ok(Form::<RenameTagForm>::extract(&req)
+        .and_then(move |form| {
+            verify_csrf_token(&expected_csrf_token, &form.csrf_token)?;
+            let mode = if form.merge {
+                rename::Mode::Merge
+            } else {
+                rename::Mode::Rename
+            };
+            let planned = rename::plan(
+                &*state.data.read(),
+                form.old.trim(),
+                form.new.trim(),
+                mode,
+            );
+            let (changes, mut error) = match planned {
+                Ok(changes) => (Some(changes), None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            let plan_hash = changes
+                .as_ref()
+                .map(|changes| rename::plan_hash(changes));
+            let mut applied = false;
+            if form.apply {
+                if let Some(ref changes) = changes {
+                    if plan_hash.as_ref() != Some(&form.plan_hash) {
+                        error = Some(
+                            "The changes differ from the preview, \
+                             check them and apply again"
+                                .into(),
+                        );
+                    } else if let Err(e) =
+                        rename::apply(&state.data, changes)
+                    {
+                        auditor.record(&format!(
+                            "Failed to rename tag #{}: {}",
+                            tag::normalize(form.old.trim()),
+                            e
+                        ));
+                        error = Some(e.to_string());
+                    } else {
+                        applied = true;
+                        auditor.record(&format!(
+                            "{} tag #{} to #{} in {} files",
+                            if form.merge { "Merge" } else { "Rename" },
+                            tag::normalize(form.old.trim()),
+                            tag::normalize(form.new.trim()),
+                            changes.len()
+                        ));
+                    }
+                }
+            }
+
+            let mut base = tpl::base::Data::from(&req);
+            base.title = "Tags".into();
+            let body = tpl::tags::page(&tpl::tags::Data {
+                base: base,
+                old: form.old.clone(),
+                new: form.new.clone(),
+                merge: form.merge,
+                error: error,
+                plan_hash: plan_hash.filter(|_| !applied),
+                diffs: changes.map(|changes| {
+                    changes
+                        .iter()
+                        .map(|change| tpl::tags::FileDiff {
+                            file_name: change.file_name(&state.data_dir),
+                            diff: change.diff(),
+                        })
+                        .collect()
+                }),
+                applied: applied,
+            });
+            Ok(HttpResponse::Ok().body(body.render_to_vec()))
+        })
+        .responder())
+}
+
+/// Most commits shown in the history of a page with `--git`
+const HISTORY_ENTRIES: usize = 100;
+
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    #[serde(default)]
+    rev: Option<String>,
+}
+
+fn history_get(req: HttpRequest<State>) -> Result<HttpResponse> {
+    let query = Query::<HistoryQuery>::extract(&req)?;
+    let tags_path =
+        String::from("/") + req.match_info().get("tags").unwrap_or("");
+
+    let data = req.state().data.clone();
+    let data_read = data.read();
+    let (tags, _) = data_read.url_tags(tags_path.as_str());
+    let page_id = data_read.lookup(tags)?;
+    let page = data_read.pages_by_id.get(&page_id).unwrap().clone();
+    let path = data_read.path_by_id.get(&page_id).unwrap().clone();
+    drop(data_read);
+
+    // with git, its log is the authoritative history
+    let (revisions, revision_md) = if let Some(git) = data.git() {
+        let md = match query.rev {
+            Some(ref id) => Some(git.read(&path, id)?),
+            None => None,
+        };
+        (git.log(&path, HISTORY_ENTRIES)?, md)
+    } else if let Some(history) = data.history() {
+        let md = match query.rev {
+            Some(ref id) => Some(history.read(&path, id)?),
+            None => None,
+        };
+        (history.revisions(&path)?, md)
+    } else {
+        return Ok(HttpResponse::NotFound().body("No history"));
+    };
+
+    let revision = match (query.rev.clone(), revision_md) {
+        (Some(id), Some(md)) => Some(tpl::history::ShownRevision {
+            id: id,
+            page: Page::from_markdown(md, data.read().tag_grammar()),
+        }),
+        _ => None,
+    };
+
+    let mut base = tpl::base::Data::from(&req);
+    base.title = format!("History: {}", page.title);
+    let body = tpl::history::page(&tpl::history::Data {
+        base: base,
+        revisions: revisions,
+        page: page,
+        revision: revision,
+    });
+    Ok(HttpResponse::Ok().body(body.render_to_vec()))
+}
+
+fn broken_links_get(req: HttpRequest<State>) -> Result<HttpResponse> {
+    require(&req, Access::Read)?;
+    let reports = check::check_links(&*req.state().data.read());
+
+    let mut base = tpl::base::Data::from(&req);
+    base.title = "Broken links".into();
+    let body = tpl::broken_links::page(&tpl::broken_links::Data {
+        base: base,
+        reports: reports,
+    });
+    Ok(HttpResponse::Ok().body(body.render_to_vec()))
+}
+
+const RECENT_PAGES: usize = 50;
+
+/// Most recently modified first
+fn sort_by_modified(pages: &mut Vec<Page>) {
+    pages.sort_by(|n, m| m.modified.cmp(&n.modified));
+}
+
+fn recent_get(req: HttpRequest<State>) -> Result<HttpResponse> {
+    let mut pages: Vec<_> =
+        req.state().data.read().pages_by_id.values().cloned().collect();
+    sort_by_modified(&mut pages);
+    pages.truncate(RECENT_PAGES);
+
+    let mut base = tpl::base::Data::from(&req);
+    base.title = "Recent changes".into();
+    let body = tpl::recent::page(&tpl::recent::Data {
+        base: base,
+        pages: pages,
+    });
+    Ok(HttpResponse::Ok().body(body.render_to_vec()))
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct IndexQuery {
+    sort: Option<String>,
+}
+
 fn get_index(
     req: &HttpRequest<State>,
     match_: &data::Match,
@@ -310,13 +1038,21 @@ fn get_index(
     page_ids: &[PageId],
     data: &data::State,
 ) -> Result<HttpResponse> {
+    let query = Query::<IndexQuery>::extract(req)?;
+    let sort_by_modified =
+        query.sort.as_ref().map(String::as_str) == Some("modified");
+
     let mut pages: Vec<_> = page_ids
         .iter()
         .map(|page_id| {
             data.pages_by_id.get(&page_id).unwrap().clone()
         })
         .collect();
-    pages.sort_by(|n, m| n.title.cmp(&m.title));
+    if sort_by_modified {
+        sort_by_modified(&mut pages);
+    } else {
+        pages.sort_by(|n, m| n.title.cmp(&m.title));
+    }
     let mut base = tpl::base::Data::from(req);
 
     base.title = if match_.matching_tags.is_empty() {
@@ -330,62 +1066,107 @@ fn get_index(
         cur_url: cur_url.into(),
         narrowing_tags: match_.narrowing_tags.clone(),
         matching_tags: match_.matching_tags.clone(),
+        sort_by_modified: sort_by_modified,
     });
 
     Ok(HttpResponse::Ok().body(body.render_to_vec()))
 }
 
-fn url_to_tags(url: &str) -> (Vec<String>, bool) {
-    let mut tags: Vec<_> =
-        url.split("/").skip(1).map(Into::into).collect();
-
-    let prefer_exact = if tags.last() == Some(&"".into()) {
-        tags.pop();
-        false
-    } else {
-        true
-    };
-
-    (tags, prefer_exact)
-}
-
 #[derive(Debug, Deserialize)]
 struct SearchQuery {
     q: String,
+    #[serde(default)]
+    mode: Option<String>,
 }
 
-fn search_get(
-    query: Query<SearchQuery>,
-) -> Result<HttpResponse> {
-    let tags: Vec<String> = query
-        .q
+impl SearchQuery {
+    fn is_text(&self) -> bool {
+        self.mode.as_ref().map(|m| m.as_str()) == Some("text")
+    }
+}
+
+/// Maximum number of full-text search results displayed
+const MAX_SEARCH_RESULTS: usize = 100;
+
+fn query_to_tags_url(q: &str) -> String {
+    let tags: Vec<String> = q
         .trim()
         .split(|c| c == ' ' || c == ',')
         .filter(|s| s != &"")
-        .map(Into::into)
+        .map(|s| tag::to_url_segment(&tag::normalize(s)))
         .collect();
-    Ok(redirect_to(
-        (String::from("/") + tags.join("/").as_str()).as_str(),
-    ))
+    String::from("/") + tags.join("/").as_str()
+}
+
+fn query_to_text_search_url(q: &str) -> String {
+    String::from("/~search?")
+        + url::form_urlencoded::Serializer::new(String::new())
+            .append_pair("q", q)
+            .append_pair("mode", "text")
+            .finish()
+            .as_str()
+}
+
+fn search_text(
+    req: &HttpRequest<State>,
+    q: &str,
+) -> Result<HttpResponse> {
+    let query = search::TextQuery::parse(q);
+    let data = req.state().data.read();
+
+    let results = if query.is_empty() {
+        vec![]
+    } else {
+        data.search_text(&query)
+            .iter()
+            .take(MAX_SEARCH_RESULTS)
+            .map(|hit| {
+                let page = data.pages_by_id.get(&hit.page_id).unwrap();
+                tpl::search::SearchResult {
+                    page: page.clone(),
+                    snippet: search::snippet(
+                        &markdown::plain_text(&page.md),
+                        &query.terms,
+                    ),
+                }
+            })
+            .collect()
+    };
+
+    let q = search::escape_html(q);
+    let mut base = tpl::base::Data::from(req);
+    base.title = format!("Search: {}", q);
+    let body = tpl::search::page(&tpl::search::Data {
+        base: base,
+        query: q,
+        results: results,
+    });
+    Ok(HttpResponse::Ok().body(body.render_to_vec()))
+}
+
+fn search_get(req: HttpRequest<State>) -> Result<HttpResponse> {
+    let query = Query::<SearchQuery>::extract(&req)?;
+    if query.is_text() {
+        search_text(&req, query.q.as_str())
+    } else {
+        Ok(redirect_to(query_to_tags_url(&query.q).as_str()))
+    }
 }
 
 fn search_post(
     query: Form<SearchQuery>,
 ) -> Result<HttpResponse> {
-    let tags: Vec<String> = query
-        .q
-        .trim()
-        .split(|c| c == ' ' || c == ',')
-        .filter(|s| s != &"")
-        .map(Into::into)
-        .collect();
-    Ok(redirect_to_303(
-        (String::from("/") + tags.join("/").as_str()).as_str(),
-    ))
+    if query.is_text() {
+        Ok(redirect_to_303(
+            query_to_text_search_url(&query.q).as_str(),
+        ))
+    } else {
+        Ok(redirect_to_303(query_to_tags_url(&query.q).as_str()))
+    }
 }
 
 fn new_page(req: HttpRequest<State>) -> Result<HttpResponse> {
-    assert_is_authorized(&req)?;
+    require(&req, Access::Write)?;
     let cur_url = req.path();
 
     let mut base = tpl::base::Data::from(&req);
@@ -397,15 +1178,216 @@ fn new_page(req: HttpRequest<State>) -> Result<HttpResponse> {
     Ok(HttpResponse::Ok().body(body.render_to_vec()))
 }
 
+fn get_page(
+    req: &HttpRequest<State>,
+    data: &data::State,
+    page_id: PageId,
+    cur_url: &str,
+    narrowing_tags: data::NarrowingTagsSet,
+) -> Result<HttpResponse> {
+    let page = data.pages_by_id.get(&page_id).unwrap();
+    let mut base = tpl::base::Data::from(req);
+    base.title = page.title.clone();
+    let body = tpl::view::page(&tpl::view::Data {
+        base: base,
+        page: page.clone(),
+        cur_url: cur_url.into(),
+        narrowing_tags: narrowing_tags,
+        backlinks: data
+            .backlinks(page_id)
+            .into_iter()
+            .cloned()
+            .collect(),
+        conflicts: data
+            .conflicting_pages(page_id)
+            .into_iter()
+            .map(|id| req.state().page_file(data, id))
+            .collect(),
+    });
+    Ok(HttpResponse::Ok().body(body.render_to_vec()))
+}
+
+fn get_query(
+    req: &HttpRequest<State>,
+    query_text: &str,
+    cur_url: &str,
+) -> Result<HttpResponse> {
+    let query = match query::Query::parse(query_text) {
+        Ok(query) => query,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().body(format!(
+                "Invalid query: {}",
+                search::escape_html(&e.to_string())
+            )))
+        }
+    };
+    let data = req.state().data.read();
+    let match_ = data.find_query_match(&query);
+
+    if Query::<FormatQuery>::extract(req)?.is("atom") {
+        return feed_response(req, &match_, &*data);
+    }
+    if wants_json(req)? {
+        return Ok(json_response(&match_, &*data));
+    }
+
+    match match_.type_ {
+        MatchType::One(page_id) => get_page(
+            req,
+            &*data,
+            page_id,
+            cur_url,
+            match_.narrowing_tags.clone(),
+        ),
+        MatchType::Many(ref page_ids) => get_index(
+            req,
+            &match_,
+            cur_url,
+            page_ids.as_slice(),
+            &*data,
+        ),
+        MatchType::None => {
+            Ok(HttpResponse::Ok().body(format!("Not Found :(")))
+        }
+    }
+}
+
+fn query_get(req: HttpRequest<State>) -> Result<HttpResponse> {
+    let query_text =
+        req.match_info().get("query").unwrap_or("").to_owned();
+    get_query(&req, query_text.as_str(), req.path())
+}
+
+const FEED_ENTRIES: usize = 50;
+
+/// Like `https://wiki.example.com`, as seen by the client
+fn base_url(req: &HttpRequest<State>) -> String {
+    let info = req.connection_info();
+    format!("{}://{}", info.scheme(), info.host())
+}
+
+/// Atom feed of the pages of `match_`, for tag and query urls alike
+fn feed_response(
+    req: &HttpRequest<State>,
+    match_: &data::Match,
+    data: &data::State,
+) -> Result<HttpResponse> {
+    let page_ids = match match_.type_ {
+        MatchType::None => vec![],
+        MatchType::One(page_id) => vec![page_id],
+        MatchType::Many(ref page_ids) => page_ids.clone(),
+    };
+    let mut pages: Vec<Page> = page_ids
+        .iter()
+        .map(|page_id| data.pages_by_id.get(page_id).unwrap().clone())
+        .collect();
+    feed::sort_pages(&mut pages);
+    pages.truncate(FEED_ENTRIES);
+
+    let site = &req.state().site_settings;
+    let title = if match_.matching_tags.is_empty() {
+        site.short_name.clone()
+    } else {
+        format!(
+            "{}: {}",
+            site.short_name,
+            match_.matching_tags.join("/")
+        )
+    };
+    let author = if site.author.is_empty() {
+        &site.short_name
+    } else {
+        &site.author
+    };
+    let base_url = base_url(req);
+    let feed_url = format!("{}{}", base_url, req.uri());
+
+    let xml = feed::atom(
+        &feed::Feed {
+            title: &title,
+            author: author,
+            base_url: &base_url,
+            feed_url: &feed_url,
+        },
+        &pages,
+    );
+    Ok(HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(xml))
+}
+
+fn feed_get(req: HttpRequest<State>) -> Result<HttpResponse> {
+    let url =
+        format!("/{}", req.match_info().get("tags").unwrap_or(""));
+    let data = req.state().data.read();
+    if query::is_query(&url[1..]) {
+        let query = match query::Query::parse(&url[1..]) {
+            Ok(query) => query,
+            Err(e) => {
+                return Ok(HttpResponse::BadRequest().body(format!(
+                    "Invalid query: {}",
+                    search::escape_html(&e.to_string())
+                )))
+            }
+        };
+        let match_ = data.find_query_match(&query);
+        return feed_response(&req, &match_, &*data);
+    }
+    let (tags, prefer_exact) = data.url_tags(&url);
+    let match_ = data.find_best_match(tags, prefer_exact);
+    feed_response(&req, &match_, &*data)
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct FormatQuery {
+    format: Option<String>,
+}
+
+impl FormatQuery {
+    fn is(&self, format: &str) -> bool {
+        self.format.as_ref().map(String::as_str) == Some(format)
+    }
+}
+
+/// Respond with `api::MatchResponse` instead of html
+fn wants_json(req: &HttpRequest<State>) -> Result<bool> {
+    let format = Query::<FormatQuery>::extract(req)?;
+    let accepts_json = req
+        .headers()
+        .get(http::header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .map(|accept| accept.contains("application/json"))
+        .unwrap_or(false);
+    Ok(format.is("json") || accepts_json)
+}
+
+fn json_response(
+    match_: &data::Match,
+    data: &data::State,
+) -> HttpResponse {
+    HttpResponse::Ok().json(api::MatchResponse::new(match_, data))
+}
+
 fn get(req: HttpRequest<State>) -> Result<HttpResponse> {
     let cur_url = req.path();
-    let (tags, prefer_exact) = url_to_tags(cur_url);
+    if query::is_query(&cur_url[1..]) {
+        return get_query(&req, &cur_url[1..], cur_url);
+    }
     let data = req.state().data.read();
+    let (tags, prefer_exact) = data.url_tags(cur_url);
 
     let match_ =
         data.find_best_match(tags.clone(), prefer_exact);
 
-    if match_.has_unmatched_tags() {
+    if Query::<FormatQuery>::extract(&req)?.is("atom") {
+        return feed_response(&req, &match_, &*data);
+    }
+    if wants_json(&req)? {
+        return Ok(json_response(&match_, &*data));
+    }
+
+    // drop unmatched tags, replace aliases with canonical tags
+    if match_.has_unmatched_tags() || match_.matching_tags != tags {
         return Ok(redirect_to(
             match_.to_precise_url(prefer_exact).as_str(),
         ));
@@ -421,15 +1403,13 @@ fn get(req: HttpRequest<State>) -> Result<HttpResponse> {
                     page.to_full_url(prefer_exact).as_str(),
                 ));
             }
-            let mut base = tpl::base::Data::from(&req);
-            base.title = page.title.clone();
-            let body = tpl::view::page(&tpl::view::Data {
-                base: base,
-                page: page.clone(),
-                cur_url: cur_url.into(),
-                narrowing_tags: match_.narrowing_tags,
-            });
-            Ok(HttpResponse::Ok().body(body.render_to_vec()))
+            get_page(
+                &req,
+                &*data,
+                page_id,
+                cur_url,
+                match_.narrowing_tags,
+            )
         }
         MatchType::Many(ref page_ids) => get_index(
             &req,
@@ -449,6 +1429,76 @@ struct State {
     data: data::SyncState,
     opts: Opts,
     site_settings: Arc<Site>,
+    /// Canonicalized `opts.data_dir`
+    data_dir: PathBuf,
+    audit: Arc<AuditLog>,
+    login_throttle: Arc<LoginThrottle>,
+}
+
+impl State {
+    /// Url viewing a page by its file, for pages that can't be told
+    /// apart by tags
+    fn file_url(&self, path: &Path) -> Option<String> {
+        let rel_path = path.strip_prefix(&self.data_dir).ok()?;
+        let rel_path = rel_path
+            .iter()
+            .map(|c| c.to_string_lossy().into_owned())
+            .collect::<Vec<_>>()
+            .join("/");
+        Some(
+            String::from("/~file/")
+                + utf8_percent_encode(&rel_path, DEFAULT_ENCODE_SET)
+                    .to_string()
+                    .as_str(),
+        )
+    }
+
+    fn file_url_to_path(&self, encoded: &str) -> Option<PathBuf> {
+        let rel_path = percent_decode(encoded.as_bytes())
+            .decode_utf8()
+            .ok()?;
+        let path = self.data_dir.join(&*rel_path).canonicalize().ok()?;
+        if path.starts_with(&self.data_dir) {
+            Some(path)
+        } else {
+            None
+        }
+    }
+
+    /// Page a `PUT` or `DELETE` to `url` is about, addressed by
+    /// tags or by its `/~file/...` url
+    fn page_for_url(
+        &self,
+        url: &str,
+    ) -> crate::Result<(PageId, PathBuf)> {
+        let data = self.data.read();
+        let page_id = if url.starts_with("/~file/") {
+            self.file_url_to_path(&url["/~file/".len()..])
+                .and_then(|path| data.pages_by_path.get(&path).cloned())
+                .ok_or_else(|| format_err!("Not found"))?
+        } else {
+            let (url_tags, _) = data.url_tags(url);
+            data.lookup(url_tags)?
+        };
+        Ok((page_id, data.path_by_id[&page_id].clone()))
+    }
+
+    fn page_file(
+        &self,
+        data: &data::State,
+        page_id: PageId,
+    ) -> tpl::conflicts::PageFile {
+        let path = data.path_by_id.get(&page_id).unwrap();
+        tpl::conflicts::PageFile {
+            page: data.pages_by_id.get(&page_id).unwrap().clone(),
+            file_url: self.file_url(path).unwrap_or_default(),
+            file_name: path
+                .strip_prefix(&self.data_dir)
+                .unwrap_or(path)
+                .display()
+                .to_string(),
+        }
+    }
 }
 
 struct Logger;
@@ -478,12 +1528,30 @@ impl<'a> From<&'a HttpRequest<State>>
         Self {
             title: req.state().site_settings.short_name.clone(),
             site_settings: &req.state().site_settings,
-            can_edit: can_edit(req).unwrap_or(false),
+            can_edit: Access::extract(req)
+                .map(Access::can_write)
+                .unwrap_or(false),
             can_login: can_login(req),
+            csrf_token: csrf_token(req).unwrap_or_default(),
         }
     }
 }
 
+fn session_backend(site_settings: &Site) -> CookieSessionBackend {
+    let cookie = &site_settings.cookie;
+    let backend = CookieSessionBackend::signed(&site_settings.web_salt)
+        .secure(cookie.secure)
+        .same_site(match cookie.same_site {
+            settings::SameSite::Strict => cookie::SameSite::Strict,
+            settings::SameSite::Lax => cookie::SameSite::Lax,
+            settings::SameSite::None => cookie::SameSite::None,
+        });
+    match cookie.max_age_secs {
+        Some(secs) => backend.max_age(chrono::Duration::seconds(secs)),
+        None => backend,
+    }
+}
+
 pub fn start(
     data: data::SyncState,
     site_settings: Site,
@@ -493,6 +1561,12 @@ pub fn start(
         data: data,
         opts: opts.clone(),
         site_settings: Arc::new(site_settings.clone()),
+        data_dir: opts
+            .data_dir
+            .canonicalize()
+            .expect("data dir must exist"),
+        audit: Arc::new(AuditLog::new(&opts.data_dir)),
+        login_throttle: Arc::new(LoginThrottle::new()),
     };
 
     let mut listenfd = listenfd::ListenFd::from_env();
@@ -500,18 +1574,47 @@ pub fn start(
     let server = server::new(move || {
         let app = App::with_state(state.clone())
             .middleware(Logger)
-            .middleware(SessionStorage::new(
-                // TODO
-                CookieSessionBackend::signed(
-                    &site_settings.web_salt,
-                ).secure(false),
-            ))
+            .middleware(SessionStorage::new(session_backend(
+                &site_settings,
+            )))
             .route("/~login", http::Method::GET, login_get)
             .route("/~login", http::Method::POST, login_post)
             .route("/~logout", http::Method::POST, logout)
             .route("/~search", http::Method::GET, search_get)
             .route("/~search", http::Method::POST, search_post)
-            .route("/~new", http::Method::GET, new_page);
+            .route("/~new", http::Method::GET, new_page)
+            .route("/~q/{query:.*}", http::Method::GET, query_get)
+            .route("/~status", http::Method::GET, status_get)
+            .route("/~file/{path:.*}", http::Method::GET, file_get)
+            .route("/~file/{path:.*}", http::Method::PUT, put)
+            .route("/~file/{path:.*}", http::Method::DELETE, delete)
+            .route(
+                "/~history/{tags:.*}",
+                http::Method::GET,
+                history_get,
+            )
+            .route("/~conflicts", http::Method::GET, conflicts_get)
+            .route(
+                "/~conflicts/add-tag",
+                http::Method::POST,
+                conflicts_add_tag_post,
+            )
+            .route(
+                "/~broken-links",
+                http::Method::GET,
+                broken_links_get,
+            )
+            .route("/~recent", http::Method::GET, recent_get)
+            .route("/~audit", http::Method::GET, audit_get)
+            .route("/~tags", http::Method::GET, tags_get)
+            .route("/~tags", http::Method::POST, tags_post)
+            .route("/~feed/{tags:.*}", http::Method::GET, feed_get)
+            .route("/~trash", http::Method::GET, trash_get)
+            .route(
+                "/~trash/restore",
+                http::Method::POST,
+                trash_restore_post,
+            );
         let app = if let Some(dir) = opts.theme_dir.clone() {
             app.handler("/~theme", fs::StaticFiles::new(dir))
         } else {
@@ -525,6 +1628,7 @@ pub fn start(
             r.get().f(get);
             r.post().f(post);
             r.put().f(put);
+            r.delete().f(delete);
         })
     });
     if let Some(l) = listenfd.take_tcp_listener(0).unwrap() {
@@ -533,3 +1637,243 @@ pub fn start(
         server.bind("127.0.0.1:8080").unwrap()
     }.run();
 }
+
+#[cfg(test)]
+fn test_state(
+    dir: &Path,
+    local: bool,
+    site_settings: Site,
+) -> State {
+    State {
+        data: data::SyncState::new(),
+        opts: Opts {
+            data_dir: dir.to_owned(),
+            theme_dir: None,
+            command: None,
+            local: local,
+            git: false,
+        },
+        site_settings: Arc::new(site_settings),
+        data_dir: dir.canonicalize().unwrap(),
+        audit: Arc::new(AuditLog::new(dir)),
+        login_throttle: Arc::new(LoginThrottle::new()),
+    }
+}
+
+#[cfg(test)]
+fn status_code(res: Result<HttpResponse>) -> StatusCode {
+    match res {
+        Ok(resp) => resp.status(),
+        Err(e) => e.as_response_error().error_response().status(),
+    }
+}
+
+#[test]
+fn authorization_anonymous() {
+    use actix_web::test::TestRequest;
+
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let state = test_state(dir.path(), false, Site::default());
+
+    let req = TestRequest::with_state(state.clone()).finish();
+    assert_eq!(Access::extract(&req).unwrap(), Access::Anonymous);
+    assert_eq!(
+        status_code(TestRequest::with_state(state.clone()).run(status_get)),
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        status_code(TestRequest::with_state(state).run(new_page)),
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[test]
+fn authorization_local() {
+    use actix_web::test::TestRequest;
+
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let state = test_state(dir.path(), true, Site::default());
+
+    let req = TestRequest::with_state(state.clone()).finish();
+    assert_eq!(Access::extract(&req).unwrap(), Access::Write);
+    assert_eq!(
+        status_code(TestRequest::with_state(state.clone()).run(status_get)),
+        StatusCode::OK
+    );
+    assert_eq!(
+        status_code(TestRequest::with_state(state).run(new_page)),
+        StatusCode::OK
+    );
+}
+
+#[test]
+fn authorization_tokens() {
+    use crate::settings::TokenScope;
+    use actix_web::test::TestRequest;
+
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let mut site = Site::default();
+    let read = site
+        .create_token("read".into(), TokenScope::Read)
+        .unwrap();
+    let read_write = site
+        .create_token("read-write".into(), TokenScope::ReadWrite)
+        .unwrap();
+    let state = test_state(dir.path(), false, site);
+
+    let with_token = |token: &str| {
+        TestRequest::with_state(state.clone()).header(
+            http::header::AUTHORIZATION,
+            format!("Bearer {}", token),
+        )
+    };
+
+    assert_eq!(
+        Access::extract(&with_token(&read).finish()).unwrap(),
+        Access::Read
+    );
+    assert_eq!(
+        status_code(with_token(&read).run(status_get)),
+        StatusCode::OK
+    );
+    assert_eq!(
+        status_code(with_token(&read).run(new_page)),
+        StatusCode::FORBIDDEN
+    );
+
+    assert_eq!(
+        Access::extract(&with_token(&read_write).finish()).unwrap(),
+        Access::Write
+    );
+    assert_eq!(
+        status_code(with_token(&read_write).run(new_page)),
+        StatusCode::OK
+    );
+
+    assert!(Access::extract(&with_token("wrong").finish()).is_err());
+    assert_eq!(
+        status_code(with_token("wrong").run(status_get)),
+        StatusCode::UNAUTHORIZED
+    );
+}
+
+#[test]
+fn authorization_other_schemes() {
+    use crate::settings::TokenScope;
+    use actix_web::test::TestRequest;
+
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    let mut site = Site::default();
+    let token = site
+        .create_token("read-write".into(), TokenScope::ReadWrite)
+        .unwrap();
+    let state = test_state(dir.path(), true, site);
+
+    // Basic credentials of a reverse proxy in front of the wiki
+    let basic = TestRequest::with_state(state.clone())
+        .header(http::header::AUTHORIZATION, "Basic dXNlcjpwYXNz")
+        .finish();
+    assert_eq!(Access::extract(&basic).unwrap(), Access::Write);
+    assert!(expected_csrf_token(&basic).is_some());
+
+    let bearer = TestRequest::with_state(state.clone())
+        .header(
+            http::header::AUTHORIZATION,
+            format!("Bearer {}", token),
+        )
+        .finish();
+    assert!(expected_csrf_token(&bearer).is_none());
+
+    let wrong = TestRequest::with_state(state)
+        .header(http::header::AUTHORIZATION, "Bearer wrong")
+        .finish();
+    assert!(expected_csrf_token(&wrong).is_some());
+}
+
+#[test]
+fn csrf_tokens() {
+    let expected = Some("abc".to_string());
+    assert!(verify_csrf_token(&expected, "abc").is_ok());
+    assert!(verify_csrf_token(&expected, "abd").is_err());
+    assert!(verify_csrf_token(&expected, "").is_err());
+
+    // a session without a token can't be matched
+    assert!(verify_csrf_token(&Some("".into()), "").is_err());
+
+    // exempt
+    assert!(verify_csrf_token(&None, "").is_ok());
+}
+
+#[test]
+fn non_ascii_tag_urls() {
+    let page = Page::from_markdown(
+        "# Notes\n#Cieżarkiewicz #łódź".into(),
+        &Default::default(),
+    );
+    let state = data::State::new();
+    let url = page.url();
+    assert_eq!(url, "/cie%C5%BCarkiewicz/%C5%82%C3%B3d%C5%BA");
+    assert_eq!(state.url_tags(&url), (page.tags.clone(), true));
+    assert_eq!(
+        state.url_tags("/Cieżarkiewicz/"),
+        (vec!["cieżarkiewicz".into()], false)
+    );
+    assert_eq!(
+        query_to_tags_url("Cieżarkiewicz, łódź"),
+        url
+    );
+}
+
+#[test]
+fn hierarchical_tag_urls() {
+    use crate::{
+        page_filter::PageFilter, settings::TagSettings, tag::Grammar,
+    };
+    use std::fs;
+
+    let dir = tempdir::TempDir::new("brainwiki").unwrap();
+    fs::write(dir.path().join("a.md"), "# A\n#lang/rust #idea")
+        .unwrap();
+    fs::write(dir.path().join("b.md"), "# B\n#lang #rust").unwrap();
+    let mut state = data::State::new();
+    state.set_tag_grammar(
+        Grammar::new(&TagSettings {
+            hierarchy_separator: Some('/'),
+            ..TagSettings::default()
+        })
+        .unwrap(),
+    );
+    state
+        .insert_from_dir(
+            dir.path(),
+            &PageFilter::load(dir.path()).unwrap(),
+        )
+        .unwrap();
+
+    let tags = |tags: &[&str]| -> Vec<String> {
+        tags.iter().map(|tag| tag.to_string()).collect()
+    };
+    let page = state
+        .pages_by_id
+        .values()
+        .find(|page| page.title == "A")
+        .unwrap();
+    assert_eq!(page.url(), "/idea/lang%2Frust");
+    assert_eq!(
+        state.url_tags(&page.url()),
+        (tags(&["idea", "lang/rust"]), true)
+    );
+    assert_eq!(
+        state.url_tags("/lang/rust/idea"),
+        (tags(&["lang/rust", "idea"]), true)
+    );
+    // separate tags win when some page has them
+    assert_eq!(
+        state.url_tags("/lang/rust"),
+        (tags(&["lang", "rust"]), true)
+    );
+    assert_eq!(
+        state.url_tags("/lang/go/"),
+        (tags(&["lang", "go"]), false)
+    );
+}